@@ -76,12 +76,9 @@ fn character_operations_benchmark(c: &mut Criterion) {
                 .iter()
                 .map(|c| {
                     // Simplified width calculation
-                    if c.is_ascii() {
-                        1
-                    } else if *c as u32 >= EMOJI_START {
-                        2 // Emoji
-                    } else if *c as u32 >= CJK_START && *c as u32 <= CJK_END {
-                        2 // CJK
+                    let cp = *c as u32;
+                    if cp >= EMOJI_START || (CJK_START..=CJK_END).contains(&cp) {
+                        2 // Emoji or CJK
                     } else {
                         1
                     }
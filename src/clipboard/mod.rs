@@ -7,11 +7,60 @@
 //! - Paste text from system clipboard
 //! - Bracketed paste mode support (protects against malicious pastes)
 //! - Cross-platform clipboard support (Linux, macOS, Windows)
+//! - OSC 52 clipboard access from applications, with a read policy
 
 use anyhow::{Context, Result};
 use arboard::Clipboard;
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use serde::{Deserialize, Serialize};
 use tracing::{debug, warn};
 
+/// Base64 engine for OSC 52 payloads (padding is optional on input)
+const OSC52_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Policy for applications reading the clipboard through OSC 52
+///
+/// Clipboard reads let any program running in the terminal (including
+/// remote ones) see what the user copied, so they are gated separately
+/// from writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardReadPolicy {
+    /// Answer clipboard read requests with empty data
+    Deny,
+    /// Ask the user before answering each request
+    #[default]
+    Ask,
+    /// Answer clipboard read requests without asking
+    Allow,
+}
+
+/// Clipboard access requested by an application through OSC 52
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardRequest {
+    /// Copy text to the clipboard
+    Store {
+        /// Selection targets from the request (e.g. `c`, `p`, `s`)
+        selection: String,
+        /// Decoded text to copy
+        text: String,
+    },
+    /// Read the clipboard and reply to the application
+    Load {
+        /// Selection targets from the request, echoed in the reply
+        selection: String,
+        /// The user must confirm the read first (`ClipboardReadPolicy::Ask`)
+        confirm: bool,
+    },
+}
+
 /// Clipboard manager
 ///
 /// Provides a simple interface for clipboard operations with automatic
@@ -105,6 +154,19 @@ pub fn format_bracketed_paste(text: &str) -> String {
     format!("\x1b[200~{}\x1b[201~", text)
 }
 
+/// Decode an OSC 52 base64 payload into text
+///
+/// Returns None if the payload is not valid base64 or not UTF-8.
+pub fn decode_osc52(data: &[u8]) -> Option<String> {
+    let bytes = OSC52_BASE64.decode(data).ok()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// Encode text as an OSC 52 base64 payload
+pub fn encode_osc52(text: &str) -> String {
+    OSC52_BASE64.encode(text)
+}
+
 /// Check if text contains potentially dangerous characters
 ///
 /// Returns true if the text contains newlines or other characters that
@@ -151,6 +213,31 @@ mod tests {
         assert_eq!(result, "\x1b[200~line1\nline2\nline3\x1b[201~");
     }
 
+    #[test]
+    fn test_osc52_roundtrip() {
+        let encoded = encode_osc52("hello ✓");
+        assert_eq!(encoded, "aGVsbG8g4pyT");
+        assert_eq!(decode_osc52(encoded.as_bytes()).as_deref(), Some("hello ✓"));
+    }
+
+    #[test]
+    fn test_osc52_decode_padding_optional() {
+        assert_eq!(decode_osc52(b"aGk=").as_deref(), Some("hi"));
+        assert_eq!(decode_osc52(b"aGk").as_deref(), Some("hi"));
+        assert_eq!(decode_osc52(b"not base64!"), None);
+    }
+
+    #[test]
+    fn test_clipboard_read_policy_serde() {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            policy: ClipboardReadPolicy,
+        }
+        let wrapper: Wrapper = toml::from_str("policy = \"allow\"").unwrap();
+        assert_eq!(wrapper.policy, ClipboardReadPolicy::Allow);
+        assert_eq!(ClipboardReadPolicy::default(), ClipboardReadPolicy::Ask);
+    }
+
     #[test]
     fn test_is_multiline_or_dangerous() {
         assert!(!is_multiline_or_dangerous("single line"));
@@ -14,6 +14,7 @@
 
 pub mod theme;
 
+use crate::clipboard::ClipboardReadPolicy;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -42,6 +43,10 @@ pub struct Config {
     
     /// Scrollback configuration
     pub scrollback: ScrollbackConfig,
+
+    /// Desktop notification configuration
+    #[serde(default)]
+    pub notifications: NotificationConfig,
 }
 
 /// Font configuration
@@ -79,6 +84,10 @@ pub struct TerminalConfig {
     /// Working directory (empty = home directory)
     #[serde(default)]
     pub working_directory: String,
+
+    /// Whether applications may read the clipboard via OSC 52 (deny/ask/allow)
+    #[serde(default)]
+    pub clipboard_read: ClipboardReadPolicy,
 }
 
 /// Key bindings configuration
@@ -145,7 +154,29 @@ pub struct ScrollbackConfig {
     pub scroll_speed: usize,
 }
 
+/// Desktop notification configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    /// Show notifications requested by applications (OSC 9/99/777)
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Only show notifications while the window is unfocused
+    #[serde(default = "default_true")]
+    pub only_when_unfocused: bool,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self { enabled: true, only_when_unfocused: true }
+    }
+}
+
 // Default value functions for serde
+fn default_true() -> bool {
+    true
+}
+
 fn default_opacity() -> f32 {
     1.0
 }
@@ -179,7 +210,13 @@ impl Default for Config {
                 ],
             },
             theme: "catppuccin-mocha".to_string(),
-            terminal: TerminalConfig { cols: 0, rows: 0, shell: String::new(), working_directory: String::new() },
+            terminal: TerminalConfig {
+                cols: 0,
+                rows: 0,
+                shell: String::new(),
+                working_directory: String::new(),
+                clipboard_read: ClipboardReadPolicy::Ask,
+            },
             keybindings: KeyBindings {
                 copy: "Ctrl+Shift+C".to_string(),
                 paste: "Ctrl+Shift+V".to_string(),
@@ -192,6 +229,7 @@ impl Default for Config {
             },
             window: WindowConfig { width: 0, height: 0, opacity: 1.0, blur: false, padding: 4 },
             scrollback: ScrollbackConfig { max_lines: 10_000, scroll_speed: 3 },
+            notifications: NotificationConfig::default(),
         }
     }
 }
@@ -302,6 +340,27 @@ mod tests {
         
         assert_eq!(config.font.family, deserialized.font.family);
         assert_eq!(config.theme, deserialized.theme);
+        assert_eq!(config.terminal.clipboard_read, deserialized.terminal.clipboard_read);
+    }
+
+    #[test]
+    fn test_clipboard_read_defaults_when_missing() {
+        let config: TerminalConfig = toml::from_str("cols = 80").unwrap();
+        assert_eq!(config.clipboard_read, ClipboardReadPolicy::Ask);
+    }
+
+    #[test]
+    fn test_notifications_default_when_missing() {
+        let config: Config = toml::from_str(
+            &toml::to_string(&Config::default()).unwrap().replace("[notifications]", "[unused]"),
+        )
+        .unwrap();
+        assert!(config.notifications.enabled);
+        assert!(config.notifications.only_when_unfocused);
+
+        let config: NotificationConfig = toml::from_str("enabled = false").unwrap();
+        assert!(!config.enabled);
+        assert!(config.only_when_unfocused);
     }
 
     #[test]
@@ -12,6 +12,7 @@
 //! - `config`: Configuration and theme system
 //! - `error`: Error types and error handling utilities
 //! - `logging`: Logging infrastructure using `tracing`
+//! - `notification`: Desktop notifications requested by applications
 //! - `pty`: PTY (Pseudo-Terminal) integration for process management
 //! - `terminal`: Terminal emulation core (grid, parser, cursor, colors, modes)
 //! - `ui`: User interface (Iced GUI application)
@@ -20,6 +21,7 @@ pub mod clipboard;
 pub mod config;
 pub mod error;
 pub mod logging;
+pub mod notification;
 pub mod pty;
 pub mod terminal;
 pub mod ui;
@@ -51,6 +51,6 @@ mod tests {
     fn test_logging_init() {
         // Note: We can't actually test init() because it can only be called once
         // This test just ensures the module compiles
-        assert!(true);
+        let _init: fn() = super::init;
     }
 }
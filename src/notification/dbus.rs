@@ -0,0 +1,152 @@
+//! Desktop notifications over D-Bus
+//!
+//! Notifications are sent to the freedesktop.org notification service
+//! (`org.freedesktop.Notifications`), which Linux desktops provide. The
+//! calls are made on a worker thread, so that a slow or hung service cannot
+//! block the UI.
+
+use super::{Notification, Notifier};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+use zbus::blocking::Connection;
+use zbus::zvariant::Value;
+
+/// Application name shown with notifications
+const APP_NAME: &str = "TermiEmu";
+
+/// Notifications waiting for the worker thread before new ones are dropped
+const QUEUE_LEN: usize = 8;
+
+/// Minimum time between two notifications with the same title
+const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Notifier showing desktop notifications through the session bus
+///
+/// An application printing notifications in a loop could flood the
+/// desktop, so notifications with the same title are limited to one per
+/// [`MIN_INTERVAL`] and the rest are dropped.
+#[derive(Debug)]
+pub struct DbusNotifier {
+    /// Queue of the worker thread sending the notifications
+    sender: SyncSender<Notification>,
+    /// Limit on notifications per title
+    rate_limit: RateLimit,
+}
+
+impl DbusNotifier {
+    /// Connect to the session bus
+    ///
+    /// Fails if there is no session bus, e.g. outside of a desktop session.
+    pub fn new() -> zbus::Result<Self> {
+        let connection = Connection::session()?;
+        let (sender, receiver) = mpsc::sync_channel::<Notification>(QUEUE_LEN);
+        thread::Builder::new().name("notifications".into()).spawn(move || {
+            for notification in receiver {
+                if let Err(e) = send(&connection, &notification) {
+                    warn!("Failed to show notification: {}", e);
+                }
+            }
+            debug!("Notification thread exiting");
+        })?;
+        Ok(Self { sender, rate_limit: RateLimit::default() })
+    }
+}
+
+impl Notifier for DbusNotifier {
+    fn notify(&mut self, notification: &Notification) {
+        if !self.rate_limit.allow(notification.title.as_deref(), Instant::now()) {
+            debug!("Dropping notification sent too soon after the last one");
+            return;
+        }
+        match self.sender.try_send(notification.clone()) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                debug!("Notification queue is full, dropping notification");
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                warn!("Notification thread is gone, dropping notification");
+            }
+        }
+    }
+}
+
+/// Time each title was last let through
+#[derive(Debug, Default)]
+struct RateLimit {
+    /// Time each recent title was let through
+    last: HashMap<Option<String>, Instant>,
+}
+
+impl RateLimit {
+    /// Check if a notification with `title` may be shown at `now`
+    fn allow(&mut self, title: Option<&str>, now: Instant) -> bool {
+        // Forget titles whose interval is over, so the map stays small
+        self.last.retain(|_, &mut sent| now.duration_since(sent) < MIN_INTERVAL);
+        let title = title.map(str::to_owned);
+        if self.last.contains_key(&title) {
+            return false;
+        }
+        self.last.insert(title, now);
+        true
+    }
+}
+
+/// Escape the characters that are markup in a notification body
+///
+/// Servers may interpret a subset of HTML in the body.
+fn escape_markup(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Call `Notify` on the notification service
+fn send(connection: &Connection, notification: &Notification) -> zbus::Result<()> {
+    let summary = notification.title.as_deref().unwrap_or(APP_NAME);
+    let body = escape_markup(&notification.body);
+    let actions: Vec<&str> = Vec::new();
+    let hints: HashMap<&str, Value<'_>> = HashMap::new();
+    // No id to replace, no icon, and the server's default timeout (-1)
+    let args = (APP_NAME, 0u32, "", summary, body.as_str(), actions, hints, -1i32);
+    connection.call_method(
+        Some("org.freedesktop.Notifications"),
+        "/org/freedesktop/Notifications",
+        Some("org.freedesktop.Notifications"),
+        "Notify",
+        &args,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_markup() {
+        assert_eq!(escape_markup("<b>a & b</b>"), "&lt;b&gt;a &amp; b&lt;/b&gt;");
+        assert_eq!(escape_markup("plain"), "plain");
+    }
+
+    #[test]
+    fn test_rate_limit_per_title() {
+        let mut limit = RateLimit::default();
+        let start = Instant::now();
+        assert!(limit.allow(Some("make"), start));
+        assert!(!limit.allow(Some("make"), start + Duration::from_millis(500)));
+        assert!(limit.allow(Some("cargo"), start));
+        assert!(limit.allow(None, start));
+        assert!(!limit.allow(None, start));
+        assert!(limit.allow(Some("make"), start + MIN_INTERVAL));
+    }
+}
@@ -0,0 +1,239 @@
+//! Desktop notifications for TermiEmu
+//!
+//! Applications ask for notifications with one of three escape sequences:
+//! - OSC 9 ; body (iTerm2 / ConEmu)
+//! - OSC 777 ; notify ; title ; body (rxvt-unicode / VTE)
+//! - OSC 99 ; metadata ; payload (kitty), possibly split into chunks
+//!
+//! The parser turns them into [`Notification`]s, which the UI hands to a
+//! [`Notifier`]. On Linux the [`DbusNotifier`] shows them on the desktop.
+
+#[cfg(target_os = "linux")]
+mod dbus;
+
+#[cfg(target_os = "linux")]
+pub use dbus::DbusNotifier;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tracing::{debug, info};
+
+/// Maximum length of a notification title or body
+const MAX_NOTIFICATION_LEN: usize = 4096;
+
+/// A notification requested by an application
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    /// Notification title, if the protocol provides one
+    pub title: Option<String>,
+    /// Notification body
+    pub body: String,
+}
+
+impl Notification {
+    /// Create a notification, stripping control characters
+    pub fn new(title: Option<&str>, body: &str) -> Self {
+        Self { title: title.map(sanitize).filter(|title| !title.is_empty()), body: sanitize(body) }
+    }
+
+    /// Parse the parameters of an OSC 9 sequence
+    ///
+    /// ConEmu uses `OSC 9 ; <number> ; ...` for other purposes (progress,
+    /// tab titles), so numeric subcommands are not notifications.
+    pub fn from_osc9(params: &[&[u8]]) -> Option<Self> {
+        let first = params.first()?;
+        if params.len() > 1 && !first.is_empty() && first.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        let body = String::from_utf8_lossy(&params.join(&b';')).into_owned();
+        Some(Self::new(None, &body)).filter(|n| !n.body.is_empty())
+    }
+
+    /// Parse the parameters of an OSC 777 sequence
+    pub fn from_osc777(params: &[&[u8]]) -> Option<Self> {
+        let [b"notify", title, body @ ..] = params else {
+            return None;
+        };
+        let title = String::from_utf8_lossy(title);
+        let body = String::from_utf8_lossy(&body.join(&b';')).into_owned();
+        Some(Self::new(Some(&title), &body))
+    }
+}
+
+/// Strip control characters and limit the length
+fn sanitize(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control()).take(MAX_NOTIFICATION_LEN).collect()
+}
+
+/// Assembles kitty OSC 99 notifications sent in chunks
+///
+/// Metadata is a colon-separated list of `key=value` pairs: `i` is the
+/// notification id, `d=0` means more chunks follow, `p` selects whether
+/// the payload is the `title` (default) or `body`, and `e=1` marks the
+/// payload as base64 encoded.
+#[derive(Debug, Default)]
+pub struct KittyNotifications {
+    /// Id, title and body of the notification being assembled
+    pending: Option<(Option<String>, String, String)>,
+}
+
+impl KittyNotifications {
+    /// Create an empty assembler
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an OSC 99 chunk, returning the notification once it is complete
+    pub fn push(&mut self, metadata: &[u8], payload: &[u8]) -> Option<Notification> {
+        let mut id = None;
+        let mut done = true;
+        let mut is_body = false;
+        let mut base64 = false;
+        for pair in metadata.split(|&b| b == b':') {
+            match pair {
+                [b'i', b'=', value @ ..] => {
+                    id = Some(String::from_utf8_lossy(value).into_owned());
+                }
+                b"d=0" => done = false,
+                b"p=body" => is_body = true,
+                b"p=title" => is_body = false,
+                b"e=1" => base64 = true,
+                _ => debug!("Ignoring OSC 99 metadata {:?}", String::from_utf8_lossy(pair)),
+            }
+        }
+
+        let payload = if base64 {
+            let Ok(decoded) = STANDARD.decode(payload) else {
+                debug!("Ignoring OSC 99 payload that is not base64 encoded");
+                return None;
+            };
+            String::from_utf8_lossy(&decoded).into_owned()
+        } else {
+            String::from_utf8_lossy(payload).into_owned()
+        };
+
+        // A chunk for a different notification abandons the pending one
+        if self.pending.as_ref().is_some_and(|(pending_id, ..)| *pending_id != id) {
+            self.pending = None;
+        }
+        let (_, title, body) =
+            self.pending.get_or_insert_with(|| (id, String::new(), String::new()));
+        let text = if is_body { body } else { title };
+        if text.len() < MAX_NOTIFICATION_LEN {
+            text.push_str(&payload);
+        }
+
+        if !done {
+            return None;
+        }
+        let (_, title, body) = self.pending.take()?;
+        Some(Notification::new(Some(&title), &body))
+    }
+}
+
+/// Displays notifications to the user
+pub trait Notifier {
+    /// Show a notification
+    fn notify(&mut self, notification: &Notification);
+}
+
+/// Notifier that only logs notifications
+///
+/// Used where no desktop backend is available, and in tests, where it also
+/// keeps a history of the notifications.
+#[derive(Debug, Default)]
+pub struct LoggingNotifier {
+    #[cfg(test)]
+    sent: Vec<Notification>,
+}
+
+impl LoggingNotifier {
+    /// Create a logging notifier
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the notifications shown so far
+    #[cfg(test)]
+    pub fn sent(&self) -> &[Notification] {
+        &self.sent
+    }
+}
+
+impl Notifier for LoggingNotifier {
+    fn notify(&mut self, notification: &Notification) {
+        info!(
+            "Notification: {}: {}",
+            notification.title.as_deref().unwrap_or("TermiEmu"),
+            notification.body
+        );
+        #[cfg(test)]
+        self.sent.push(notification.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc9_notification() {
+        assert_eq!(
+            Notification::from_osc9(&[b"Build done; 0 errors"]),
+            Some(Notification { title: None, body: "Build done; 0 errors".into() })
+        );
+        assert_eq!(
+            Notification::from_osc9(&[b"a", b"b"]),
+            Some(Notification { title: None, body: "a;b".into() })
+        );
+        // ConEmu progress reports are not notifications
+        assert_eq!(Notification::from_osc9(&[b"4", b"1", b"50"]), None);
+        assert_eq!(Notification::from_osc9(&[b""]), None);
+    }
+
+    #[test]
+    fn test_osc777_notification() {
+        assert_eq!(
+            Notification::from_osc777(&[b"notify", b"make", b"done\x07"]),
+            Some(Notification { title: Some("make".into()), body: "done".into() })
+        );
+        assert_eq!(Notification::from_osc777(&[b"other", b"x"]), None);
+        assert_eq!(Notification::from_osc777(&[b"notify"]), None);
+    }
+
+    #[test]
+    fn test_kitty_notification_single_chunk() {
+        let mut kitty = KittyNotifications::new();
+        assert_eq!(
+            kitty.push(b"", b"Hello"),
+            Some(Notification { title: Some("Hello".into()), body: String::new() })
+        );
+    }
+
+    #[test]
+    fn test_kitty_notification_chunks() {
+        let mut kitty = KittyNotifications::new();
+        assert_eq!(kitty.push(b"i=1:d=0", b"Build"), None);
+        assert_eq!(kitty.push(b"i=1:d=0:p=body:e=1", b"ZmluaXNoZWQ="), None);
+        assert_eq!(
+            kitty.push(b"i=1:p=body", b" ok"),
+            Some(Notification { title: Some("Build".into()), body: "finished ok".into() })
+        );
+    }
+
+    #[test]
+    fn test_kitty_notification_new_id_discards_pending() {
+        let mut kitty = KittyNotifications::new();
+        assert_eq!(kitty.push(b"i=1:d=0", b"stale"), None);
+        assert_eq!(
+            kitty.push(b"i=2", b"fresh"),
+            Some(Notification { title: Some("fresh".into()), body: String::new() })
+        );
+    }
+
+    #[test]
+    fn test_logging_notifier_records() {
+        let mut notifier = LoggingNotifier::new();
+        notifier.notify(&Notification::new(None, "hi"));
+        assert_eq!(notifier.sent().len(), 1);
+    }
+}
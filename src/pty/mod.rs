@@ -235,6 +235,29 @@ impl Pty {
         Ok(())
     }
 
+    /// Get the working directory of the foreground process
+    ///
+    /// This reads `/proc/<pid>/cwd` of the PTY's foreground process group
+    /// leader, falling back to the shell itself. Returns `None` if the PTY has
+    /// not been spawned or the platform has no procfs.
+    pub fn foreground_working_directory(&self) -> Option<std::path::PathBuf> {
+        #[cfg(target_os = "linux")]
+        {
+            let pid = self
+                .master
+                .as_ref()
+                .and_then(|master| master.process_group_leader())
+                .and_then(|pid| u32::try_from(pid).ok())
+                .or_else(|| self.child.as_ref()?.process_id())?;
+            std::fs::read_link(format!("/proc/{}/cwd", pid)).ok()
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            None
+        }
+    }
+
     /// Get the current PTY configuration
     pub fn config(&self) -> &PtyConfig {
         &self.config
@@ -377,8 +400,7 @@ mod tests {
 
     #[test]
     fn test_custom_shell() {
-        let mut config = PtyConfig::default();
-        config.shell = Some("/bin/sh".to_string());
+        let config = PtyConfig { shell: Some("/bin/sh".to_string()), ..PtyConfig::default() };
 
         let pty = Pty::new(config).expect("Failed to create PTY");
         let shell = pty.detect_shell().expect("Failed to detect shell");
@@ -386,6 +408,23 @@ mod tests {
         assert_eq!(shell, "/bin/sh");
     }
 
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_foreground_working_directory() {
+        let dir = std::env::temp_dir().canonicalize().unwrap();
+        let config = PtyConfig {
+            shell: Some("/bin/sh".to_string()),
+            working_directory: Some(dir.clone()),
+            ..PtyConfig::default()
+        };
+        let mut pty = Pty::new(config).expect("Failed to create PTY");
+        assert_eq!(pty.foreground_working_directory(), None);
+
+        pty.spawn().expect("Failed to spawn PTY");
+        assert_eq!(pty.foreground_working_directory(), Some(dir));
+        pty.kill().ok();
+    }
+
     #[test]
     fn test_write_to_pty() {
         let config = PtyConfig::default();
@@ -1,12 +1,13 @@
 //! Terminal grid cell representation
 
 use super::color::Color;
+use super::extra::ExtraId;
 use bitflags::bitflags;
 
 bitflags! {
     /// Cell attribute flags
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
-    pub struct CellFlags: u8 {
+    pub struct CellFlags: u16 {
         /// Bold text
         const BOLD = 0b0000_0001;
         /// Dim/faint text
@@ -23,6 +24,108 @@ bitflags! {
         const STRIKETHROUGH = 0b0100_0000;
         /// Wide character (CJK, emoji)
         const WIDE_CHAR = 0b1000_0000;
+        /// The line continues on the next row (set on the last cell of a
+        /// soft-wrapped row)
+        const WRAPLINE = 0b0000_0001_0000_0000;
+        /// Right half of a wide character, or padding left in the last
+        /// column when a wide character wrapped early
+        const WIDE_CHAR_SPACER = 0b0000_0010_0000_0000;
+        /// Double underline (SGR 4:2, 21)
+        const DOUBLE_UNDERLINE = 0b0000_0100_0000_0000;
+        /// Curly underline (SGR 4:3)
+        const UNDERCURL = 0b0000_1000_0000_0000;
+        /// Dotted underline (SGR 4:4)
+        const DOTTED_UNDERLINE = 0b0001_0000_0000_0000;
+        /// Dashed underline (SGR 4:5)
+        const DASHED_UNDERLINE = 0b0010_0000_0000_0000;
+        /// Blinking text (SGR 5, 6)
+        const BLINK = 0b0100_0000_0000_0000;
+        /// Overlined text (SGR 53)
+        const OVERLINE = 0b1000_0000_0000_0000;
+        /// Any underline style
+        const ALL_UNDERLINES = Self::UNDERLINE.bits()
+            | Self::DOUBLE_UNDERLINE.bits()
+            | Self::UNDERCURL.bits()
+            | Self::DOTTED_UNDERLINE.bits()
+            | Self::DASHED_UNDERLINE.bits();
+    }
+}
+
+impl CellFlags {
+    /// Get the underline style, if the text is underlined
+    pub fn underline_style(self) -> Option<UnderlineStyle> {
+        [
+            UnderlineStyle::Single,
+            UnderlineStyle::Double,
+            UnderlineStyle::Curly,
+            UnderlineStyle::Dotted,
+            UnderlineStyle::Dashed,
+        ]
+        .into_iter()
+        .find(|style| self.contains(style.flag()))
+    }
+
+    /// Replace the underline style, or remove the underline with `None`
+    pub fn set_underline_style(&mut self, style: Option<UnderlineStyle>) {
+        self.remove(Self::ALL_UNDERLINES);
+        if let Some(style) = style {
+            self.insert(style.flag());
+        }
+    }
+}
+
+/// Underline styles selected with SGR 4:x
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UnderlineStyle {
+    /// Straight line (SGR 4, 4:1)
+    Single,
+    /// Two straight lines (SGR 4:2, 21)
+    Double,
+    /// Wavy line (SGR 4:3)
+    Curly,
+    /// Dotted line (SGR 4:4)
+    Dotted,
+    /// Dashed line (SGR 4:5)
+    Dashed,
+}
+
+impl UnderlineStyle {
+    /// Get the style selected by an SGR 4 subparameter
+    ///
+    /// Returns `Some(None)` for 4:0 (no underline) and `None` for unknown
+    /// styles.
+    pub fn from_sgr(style: u16) -> Option<Option<Self>> {
+        match style {
+            0 => Some(None),
+            1 => Some(Some(Self::Single)),
+            2 => Some(Some(Self::Double)),
+            3 => Some(Some(Self::Curly)),
+            4 => Some(Some(Self::Dotted)),
+            5 => Some(Some(Self::Dashed)),
+            _ => None,
+        }
+    }
+
+    /// Get the SGR 4 subparameter selecting this style
+    pub fn sgr(self) -> u16 {
+        match self {
+            Self::Single => 1,
+            Self::Double => 2,
+            Self::Curly => 3,
+            Self::Dotted => 4,
+            Self::Dashed => 5,
+        }
+    }
+
+    /// Get the cell flag marking this style
+    pub fn flag(self) -> CellFlags {
+        match self {
+            Self::Single => CellFlags::UNDERLINE,
+            Self::Double => CellFlags::DOUBLE_UNDERLINE,
+            Self::Curly => CellFlags::UNDERCURL,
+            Self::Dotted => CellFlags::DOTTED_UNDERLINE,
+            Self::Dashed => CellFlags::DASHED_UNDERLINE,
+        }
     }
 }
 
@@ -37,6 +140,9 @@ pub struct Cell {
     pub bg: Color,
     /// Text attributes (bold, italic, etc.)
     pub flags: CellFlags,
+    /// Hyperlink and combining characters, interned in the owning grid's
+    /// extra table
+    pub extra: Option<ExtraId>,
 }
 
 impl Default for Cell {
@@ -46,6 +152,7 @@ impl Default for Cell {
             fg: Color::default(),
             bg: Color::Named(super::color::NamedColor::Background),
             flags: CellFlags::empty(),
+            extra: None,
         }
     }
 }
@@ -58,7 +165,7 @@ impl Cell {
 
     /// Create a new cell with character and colors
     pub fn with_colors(c: char, fg: Color, bg: Color) -> Self {
-        Self { c, fg, bg, flags: CellFlags::empty() }
+        Self { c, fg, bg, flags: CellFlags::empty(), extra: None }
     }
 
     /// Check if this cell is empty (contains only whitespace)
@@ -91,13 +198,31 @@ mod tests {
 
     #[test]
     fn test_cell_flags() {
-        let mut cell = Cell::default();
-        cell.flags = CellFlags::BOLD | CellFlags::ITALIC;
+        let cell = Cell { flags: CellFlags::BOLD | CellFlags::ITALIC, ..Cell::default() };
         assert!(cell.flags.contains(CellFlags::BOLD));
         assert!(cell.flags.contains(CellFlags::ITALIC));
         assert!(!cell.flags.contains(CellFlags::UNDERLINE));
     }
 
+    #[test]
+    fn test_underline_style_flags() {
+        let mut flags = CellFlags::BOLD | CellFlags::UNDERLINE;
+        assert_eq!(flags.underline_style(), Some(UnderlineStyle::Single));
+
+        flags.set_underline_style(Some(UnderlineStyle::Curly));
+        assert_eq!(flags.underline_style(), Some(UnderlineStyle::Curly));
+        assert!(!flags.contains(CellFlags::UNDERLINE));
+
+        flags.set_underline_style(None);
+        assert_eq!(flags, CellFlags::BOLD);
+        assert_eq!(UnderlineStyle::from_sgr(6), None);
+    }
+
+    #[test]
+    fn test_cell_stays_small() {
+        assert!(std::mem::size_of::<Cell>() <= 16);
+    }
+
     #[test]
     fn test_cell_is_empty() {
         let cell = Cell::default();
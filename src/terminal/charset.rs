@@ -0,0 +1,203 @@
+//! Character set designation and translation
+//!
+//! VT100-compatible terminals keep four character set slots (G0-G3) that
+//! applications designate with `ESC ( F`, `ESC ) F`, `ESC * F` and `ESC + F`.
+//! One slot is invoked into GL with a locking shift (SI/SO for G0/G1, LS2/LS3
+//! for G2/G3), or for a single character with a single shift (SS2/SS3).
+//! Printable characters are translated through the invoked set before they
+//! are written into the grid, which is how `tput smacs` line drawing works.
+
+/// A character set that can be designated into one of the G0-G3 slots
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Charset {
+    /// US ASCII (no translation)
+    #[default]
+    Ascii,
+    /// DEC Special Graphics (line drawing)
+    DecSpecialGraphics,
+    /// United Kingdom national set (`#` is the pound sign)
+    Uk,
+}
+
+impl Charset {
+    /// Look up the character set for a designation final byte
+    ///
+    /// Returns None for character sets that are not supported.
+    pub fn from_designator(byte: u8) -> Option<Self> {
+        match byte {
+            b'B' => Some(Self::Ascii),
+            b'0' => Some(Self::DecSpecialGraphics),
+            b'A' => Some(Self::Uk),
+            _ => None,
+        }
+    }
+
+    /// Translate a character through this character set
+    pub fn map(self, c: char) -> char {
+        match self {
+            Self::Ascii => c,
+            Self::Uk => match c {
+                '#' => '£',
+                _ => c,
+            },
+            Self::DecSpecialGraphics => match c {
+                '_' => ' ',
+                '`' => '◆',
+                'a' => '▒',
+                'b' => '␉',
+                'c' => '␌',
+                'd' => '␍',
+                'e' => '␊',
+                'f' => '°',
+                'g' => '±',
+                'h' => '␤',
+                'i' => '␋',
+                'j' => '┘',
+                'k' => '┐',
+                'l' => '┌',
+                'm' => '└',
+                'n' => '┼',
+                'o' => '⎺',
+                'p' => '⎻',
+                'q' => '─',
+                'r' => '⎼',
+                's' => '⎽',
+                't' => '├',
+                'u' => '┤',
+                'v' => '┴',
+                'w' => '┬',
+                'x' => '│',
+                'y' => '≤',
+                'z' => '≥',
+                '{' => 'π',
+                '|' => '≠',
+                '}' => '£',
+                '~' => '·',
+                _ => c,
+            },
+        }
+    }
+}
+
+/// Designated character sets for the G0-G3 slots and the shift state
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct CharsetState {
+    /// Character sets designated into G0-G3
+    slots: [Charset; 4],
+    /// Slot invoked into GL by the last locking shift
+    active: usize,
+    /// Slot invoked for the next character only (SS2/SS3)
+    single_shift: Option<usize>,
+}
+
+impl CharsetState {
+    /// Create a charset state with ASCII in every slot
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Designate a character set into slot G`index` (0-3)
+    pub fn designate(&mut self, index: usize, charset: Charset) {
+        if let Some(slot) = self.slots.get_mut(index) {
+            *slot = charset;
+        }
+    }
+
+    /// Get the character set designated into slot G`index` (0-3)
+    pub fn slot(&self, index: usize) -> Charset {
+        self.slots.get(index).copied().unwrap_or_default()
+    }
+
+    /// Invoke slot G`index` (0-3) until the next locking shift
+    ///
+    /// SI invokes G0, SO G1, LS2 G2 and LS3 G3.
+    pub fn lock_shift(&mut self, index: usize) {
+        if index < self.slots.len() {
+            self.active = index;
+        }
+    }
+
+    /// Invoke slot G`index` (2 or 3) for the next character only (SS2/SS3)
+    pub fn single_shift(&mut self, index: usize) {
+        if index < self.slots.len() {
+            self.single_shift = Some(index);
+        }
+    }
+
+    /// Get the slot invoked into GL by the last locking shift
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// Translate a printable character through the invoked set
+    ///
+    /// A pending single shift applies to this character only.
+    pub fn translate(&mut self, c: char) -> char {
+        let index = self.single_shift.take().unwrap_or(self.active);
+        self.slots[index].map(c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_designator_lookup() {
+        assert_eq!(Charset::from_designator(b'B'), Some(Charset::Ascii));
+        assert_eq!(Charset::from_designator(b'0'), Some(Charset::DecSpecialGraphics));
+        assert_eq!(Charset::from_designator(b'A'), Some(Charset::Uk));
+        assert_eq!(Charset::from_designator(b'Z'), None);
+    }
+
+    #[test]
+    fn test_dec_special_graphics() {
+        let charset = Charset::DecSpecialGraphics;
+        let mapped: String = "lqkxmj".chars().map(|c| charset.map(c)).collect();
+        assert_eq!(mapped, "┌─┐│└┘");
+        // Characters outside the table pass through
+        assert_eq!(charset.map('A'), 'A');
+    }
+
+    #[test]
+    fn test_charset_state_translates_g0() {
+        let mut state = CharsetState::new();
+        assert_eq!(state.translate('q'), 'q');
+
+        state.designate(1, Charset::DecSpecialGraphics);
+        assert_eq!(state.translate('q'), 'q');
+
+        state.designate(0, Charset::DecSpecialGraphics);
+        assert_eq!(state.translate('q'), '─');
+        assert_eq!(state.slot(1), Charset::DecSpecialGraphics);
+    }
+
+    #[test]
+    fn test_uk_charset() {
+        assert_eq!(Charset::Uk.map('#'), '£');
+        assert_eq!(Charset::Uk.map('q'), 'q');
+    }
+
+    #[test]
+    fn test_locking_shift() {
+        let mut state = CharsetState::new();
+        state.designate(1, Charset::DecSpecialGraphics);
+        state.lock_shift(1);
+        assert_eq!(state.translate('q'), '─');
+        assert_eq!(state.translate('x'), '│');
+
+        state.lock_shift(0);
+        assert_eq!(state.translate('q'), 'q');
+        state.lock_shift(7);
+        assert_eq!(state.active(), 0);
+    }
+
+    #[test]
+    fn test_single_shift_applies_once() {
+        let mut state = CharsetState::new();
+        state.designate(2, Charset::Uk);
+        state.single_shift(2);
+        assert_eq!(state.translate('#'), '£');
+        assert_eq!(state.translate('#'), '#');
+    }
+}
@@ -12,6 +12,38 @@ pub enum CursorStyle {
     Bar,
 }
 
+impl CursorStyle {
+    /// Get the style and blinking state selected by DECSCUSR (`CSI Ps SP q`)
+    ///
+    /// 0 restores the default steady block. Returns None for unknown values.
+    pub fn from_decscusr(ps: u16) -> Option<(Self, bool)> {
+        match ps {
+            0 => Some((Self::Block, false)),
+            1 => Some((Self::Block, true)),
+            2 => Some((Self::Block, false)),
+            3 => Some((Self::Underline, true)),
+            4 => Some((Self::Underline, false)),
+            5 => Some((Self::Bar, true)),
+            6 => Some((Self::Bar, false)),
+            _ => None,
+        }
+    }
+
+    /// Get the DECSCUSR parameter that selects this style
+    pub fn decscusr(self, blinking: bool) -> u16 {
+        let steady = match self {
+            Self::Block => 2,
+            Self::Underline => 4,
+            Self::Bar => 6,
+        };
+        if blinking {
+            steady - 1
+        } else {
+            steady
+        }
+    }
+}
+
 /// Terminal cursor state
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Cursor {
@@ -23,11 +55,13 @@ pub struct Cursor {
     pub visible: bool,
     /// Cursor style
     pub style: CursorStyle,
+    /// Whether the cursor blinks (DECSCUSR or mode 12)
+    pub blinking: bool,
 }
 
 impl Default for Cursor {
     fn default() -> Self {
-        Self { col: 0, row: 0, visible: true, style: CursorStyle::default() }
+        Self { col: 0, row: 0, visible: true, style: CursorStyle::default(), blinking: false }
     }
 }
 
@@ -80,4 +114,14 @@ mod tests {
         assert_ne!(CursorStyle::Block, CursorStyle::Bar);
         assert_ne!(CursorStyle::Bar, CursorStyle::Underline);
     }
+
+    #[test]
+    fn test_decscusr_round_trip() {
+        for ps in 1..=6 {
+            let (style, blinking) = CursorStyle::from_decscusr(ps).unwrap();
+            assert_eq!(style.decscusr(blinking), ps);
+        }
+        assert_eq!(CursorStyle::from_decscusr(0), Some((CursorStyle::Block, false)));
+        assert_eq!(CursorStyle::from_decscusr(7), None);
+    }
 }
@@ -0,0 +1,119 @@
+//! Working directory reporting (OSC 7)
+//!
+//! Shells report their current directory as a `file://host/path` URI with
+//! the path percent-encoded. Directories on other hosts (e.g. inside an SSH
+//! session) are told apart, since they cannot be used to spawn local shells.
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Directory reported by an OSC 7 URI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReportedDirectory {
+    /// A directory on this machine
+    Local(PathBuf),
+    /// A directory on another host
+    Remote,
+}
+
+/// Parse an OSC 7 `file://` URI
+///
+/// The directory is local if the host is empty, `localhost` or matches
+/// `hostname` (case-insensitively). Returns None for malformed URIs.
+pub fn parse_file_uri(uri: &[u8], hostname: Option<&str>) -> Option<ReportedDirectory> {
+    let rest = uri.strip_prefix(b"file://")?;
+    let path_start = rest.iter().position(|&b| b == b'/')?;
+    let (host, path) = rest.split_at(path_start);
+
+    let host = std::str::from_utf8(host).ok()?;
+    let is_local = host.is_empty()
+        || host.eq_ignore_ascii_case("localhost")
+        || hostname.is_some_and(|name| host.eq_ignore_ascii_case(name));
+    if !is_local {
+        return Some(ReportedDirectory::Remote);
+    }
+
+    let path = percent_decode(path)?;
+    if path.contains(&0) {
+        return None;
+    }
+    Some(ReportedDirectory::Local(path_from_bytes(path)))
+}
+
+/// Get the name of the local host
+///
+/// The name is looked up once and cached.
+pub fn local_hostname() -> Option<&'static str> {
+    static HOSTNAME: OnceLock<Option<String>> = OnceLock::new();
+    HOSTNAME
+        .get_or_init(|| {
+            std::fs::read_to_string("/proc/sys/kernel/hostname")
+                .ok()
+                .or_else(|| std::env::var("HOSTNAME").ok())
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+        })
+        .as_deref()
+}
+
+/// Decode `%XX` escapes, failing on malformed escapes
+fn percent_decode(input: &[u8]) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut bytes = input.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'%' {
+            let hi = (*bytes.next()? as char).to_digit(16)?;
+            let lo = (*bytes.next()? as char).to_digit(16)?;
+            output.push((hi * 16 + lo) as u8);
+        } else {
+            output.push(byte);
+        }
+    }
+    Some(output)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(path: &str) -> Option<ReportedDirectory> {
+        Some(ReportedDirectory::Local(PathBuf::from(path)))
+    }
+
+    #[test]
+    fn test_parse_file_uri() {
+        assert_eq!(parse_file_uri(b"file:///home/user", None), local("/home/user"));
+        assert_eq!(parse_file_uri(b"file://localhost/tmp/a%20b", None), local("/tmp/a b"));
+        assert_eq!(
+            parse_file_uri(b"file://Box/srv/%E2%9C%93", Some("box")),
+            local("/srv/\u{2713}")
+        );
+    }
+
+    #[test]
+    fn test_parse_file_uri_remote_hosts() {
+        let remote = Some(ReportedDirectory::Remote);
+        assert_eq!(parse_file_uri(b"file://remote/home/user", Some("box")), remote);
+        assert_eq!(parse_file_uri(b"file://remote/home/user", None), remote);
+    }
+
+    #[test]
+    fn test_parse_file_uri_rejects_malformed() {
+        assert_eq!(parse_file_uri(b"http://localhost/tmp", None), None);
+        assert_eq!(parse_file_uri(b"file://localhost", None), None);
+        assert_eq!(parse_file_uri(b"file:///tmp/%zz", None), None);
+        assert_eq!(parse_file_uri(b"file:///tmp/%2", None), None);
+        assert_eq!(parse_file_uri(b"file:///tmp/%00", None), None);
+    }
+}
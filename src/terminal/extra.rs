@@ -0,0 +1,200 @@
+//! Out-of-line cell data
+//!
+//! Most cells only need a character, colors and flags. Everything else -- the
+//! OSC 8 hyperlink, the underline color, DECSCA protection and the zero-width
+//! characters that extend the base character into a grapheme cluster
+//! (combining marks, variation selectors, zero width joiners) -- is interned
+//! in a per-grid table, so that a cell only carries a small [`ExtraId`] and
+//! stays 16 bytes. Ids of entries no cell refers to anymore can be freed and
+//! are then reused.
+
+use super::color::Color;
+use super::hyperlink::HyperlinkId;
+use std::collections::HashMap;
+use std::num::NonZeroU16;
+use tracing::debug;
+
+/// Maximum number of zero-width characters attached to one cell
+///
+/// Longer sequences are truncated, which bounds the memory a single cell
+/// can use when an application prints a stream of combining marks.
+pub const MAX_ZEROWIDTH_CHARS: usize = 16;
+
+/// Data stored outside of a [`Cell`](super::Cell)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CellExtra {
+    /// OSC 8 hyperlink, interned in the grid's link table
+    pub hyperlink: Option<HyperlinkId>,
+    /// Underline color (SGR 58), `None` to use the foreground color
+    pub underline_color: Option<Color>,
+    /// Protected from selective erase (DECSCA)
+    pub protected: bool,
+    /// Zero-width characters following the cell's character
+    pub zerowidth: String,
+}
+
+impl CellExtra {
+    /// Check if there is nothing to store
+    pub fn is_empty(&self) -> bool {
+        self.hyperlink.is_none()
+            && self.underline_color.is_none()
+            && !self.protected
+            && self.zerowidth.is_empty()
+    }
+
+    /// Append a zero-width character, ignoring it once the limit is reached
+    pub fn push_zerowidth(&mut self, c: char) {
+        if self.zerowidth.chars().count() < MAX_ZEROWIDTH_CHARS {
+            self.zerowidth.push(c);
+        }
+    }
+}
+
+/// Handle to data interned in an [`ExtraTable`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ExtraId(NonZeroU16);
+
+impl ExtraId {
+    /// Get the index of the entry in the table
+    fn index(self) -> usize {
+        self.0.get() as usize - 1
+    }
+}
+
+/// Interned cell data referenced by grid cells
+#[derive(Clone, Debug, Default)]
+pub struct ExtraTable {
+    /// Entries, indexed by id - 1, with None for freed ids
+    extras: Vec<Option<CellExtra>>,
+    /// Reverse lookup for interning
+    ids: HashMap<CellExtra, ExtraId>,
+    /// Freed ids, reused before new ones are handed out
+    free: Vec<ExtraId>,
+}
+
+impl ExtraTable {
+    /// Create an empty table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Intern cell data, returning its id
+    ///
+    /// Empty data needs no id. Returns None if the table is full.
+    pub fn intern(&mut self, extra: &CellExtra) -> Option<ExtraId> {
+        if extra.is_empty() {
+            return None;
+        }
+        if let Some(&id) = self.ids.get(extra) {
+            return Some(id);
+        }
+
+        let id = if let Some(id) = self.free.pop() {
+            self.extras[id.index()] = Some(extra.clone());
+            id
+        } else {
+            let Some(id) = u16::try_from(self.extras.len() + 1).ok().and_then(NonZeroU16::new)
+            else {
+                debug!("Cell extra table full, dropping {:?}", extra);
+                return None;
+            };
+            self.extras.push(Some(extra.clone()));
+            ExtraId(id)
+        };
+        self.ids.insert(extra.clone(), id);
+        Some(id)
+    }
+
+    /// Look up interned cell data
+    pub fn get(&self, id: ExtraId) -> Option<&CellExtra> {
+        self.extras.get(id.index())?.as_ref()
+    }
+
+    /// Free the ids for which `keep` returns false, so that they can be
+    /// reused
+    pub fn retain(&mut self, mut keep: impl FnMut(ExtraId) -> bool) {
+        let (extras, free) = (&mut self.extras, &mut self.free);
+        self.ids.retain(|_, &mut id| {
+            if keep(id) {
+                return true;
+            }
+            extras[id.index()] = None;
+            free.push(id);
+            false
+        });
+    }
+
+    /// Get the number of interned entries
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Check if the table is empty
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zerowidth(text: &str) -> CellExtra {
+        CellExtra { zerowidth: text.into(), ..CellExtra::default() }
+    }
+
+    #[test]
+    fn test_intern_deduplicates() {
+        let mut table = ExtraTable::new();
+        let a = table.intern(&zerowidth("\u{301}")).unwrap();
+        let b = table.intern(&zerowidth("\u{301}")).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.get(a).unwrap().zerowidth, "\u{301}");
+    }
+
+    #[test]
+    fn test_empty_extra_needs_no_id() {
+        let mut table = ExtraTable::new();
+        assert_eq!(table.intern(&CellExtra::default()), None);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn test_retain_frees_ids_for_reuse() {
+        let mut table = ExtraTable::new();
+        let a = table.intern(&zerowidth("\u{301}")).unwrap();
+        let b = table.intern(&zerowidth("\u{308}")).unwrap();
+        table.retain(|id| id == b);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.get(a), None);
+
+        let c = table.intern(&zerowidth("\u{30A}")).unwrap();
+        assert_eq!(c, a);
+        assert_eq!(table.get(c).unwrap().zerowidth, "\u{30A}");
+        // A freed entry is interned anew
+        assert_ne!(table.intern(&zerowidth("\u{301}")), Some(a));
+    }
+
+    #[test]
+    fn test_full_table() {
+        let mut table = ExtraTable::new();
+        for i in 0..u16::MAX {
+            assert!(table.intern(&zerowidth(&i.to_string())).is_some());
+        }
+        assert_eq!(table.intern(&zerowidth("\u{301}")), None);
+
+        table.retain(|_| false);
+        assert!(table.is_empty());
+        assert!(table.intern(&zerowidth("\u{301}")).is_some());
+    }
+
+    #[test]
+    fn test_zerowidth_limit() {
+        let mut extra = CellExtra::default();
+        for _ in 0..MAX_ZEROWIDTH_CHARS + 4 {
+            extra.push_zerowidth('\u{301}');
+        }
+        assert_eq!(extra.zerowidth.chars().count(), MAX_ZEROWIDTH_CHARS);
+    }
+}
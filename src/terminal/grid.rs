@@ -2,8 +2,20 @@
 //!
 //! Provides a 2D grid for storing terminal cells with scrollback support.
 
-use super::cell::Cell;
-use std::collections::VecDeque;
+use super::cell::{Cell, CellFlags};
+use super::color::{Color, NamedColor};
+use super::extra::{CellExtra, ExtraId, ExtraTable};
+use super::hyperlink::{Hyperlink, HyperlinkId, HyperlinkTable};
+use super::selection::Point;
+use super::semantic::{self, CommandRecord, LineMark, SemanticMark};
+use std::collections::{HashSet, VecDeque};
+
+/// Cells scanned when reclaiming table entries per cell that has to change
+/// before the next scan
+///
+/// Once a table is full of entries that are still in use, every printed
+/// character would otherwise scan the whole grid and scrollback again.
+const RECLAIM_SCAN_RATIO: usize = 16;
 
 /// Terminal grid with scrollback buffer
 #[derive(Clone, Debug)]
@@ -18,6 +30,36 @@ pub struct Grid {
     scrollback: VecDeque<Vec<Cell>>,
     /// Maximum scrollback lines
     max_scrollback: usize,
+    /// Hyperlinks referenced by cells in the grid and scrollback
+    hyperlinks: HyperlinkTable,
+    /// Out-of-line data referenced by cells in the grid and scrollback
+    extras: ExtraTable,
+    /// Cells written or dropped since table entries were last reclaimed
+    changed_cells: usize,
+    /// Changed cells needed before table entries are reclaimed again
+    reclaim_after: usize,
+    /// Background color of erased and inserted cells
+    background: Color,
+    /// Shell integration marks of each visible row
+    marks: Vec<Vec<LineMark>>,
+    /// Shell integration marks of each scrollback line
+    scrollback_marks: VecDeque<Vec<LineMark>>,
+}
+
+/// Rectangular area of the visible grid (0-indexed, inclusive)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub top: usize,
+    pub left: usize,
+    pub bottom: usize,
+    pub right: usize,
+}
+
+impl Rect {
+    /// Create a new rectangle
+    pub const fn new(top: usize, left: usize, bottom: usize, right: usize) -> Self {
+        Self { top, left, bottom, right }
+    }
 }
 
 impl Grid {
@@ -30,7 +72,20 @@ impl Grid {
     /// * `max_scrollback` - Maximum number of scrollback lines (default 10,000)
     pub fn new(cols: usize, rows: usize, max_scrollback: usize) -> Self {
         let cells = vec![Cell::default(); cols * rows];
-        Self { cols, rows, cells, scrollback: VecDeque::new(), max_scrollback }
+        Self {
+            cols,
+            rows,
+            cells,
+            scrollback: VecDeque::new(),
+            max_scrollback,
+            hyperlinks: HyperlinkTable::new(),
+            extras: ExtraTable::new(),
+            changed_cells: 0,
+            reclaim_after: 0,
+            background: Color::Named(NamedColor::Background),
+            marks: vec![Vec::new(); rows],
+            scrollback_marks: VecDeque::new(),
+        }
     }
 
     /// Get the number of columns
@@ -74,82 +129,595 @@ impl Grid {
     pub fn set(&mut self, col: usize, row: usize, cell: Cell) {
         if let Some(target) = self.get_mut(col, row) {
             *target = cell;
+            self.changed_cells += 1;
+        }
+    }
+
+    /// Intern a hyperlink in this grid's link table
+    ///
+    /// When the table is full, links no cell refers to anymore are freed
+    /// first. Returns None if all of them are still in use, or if too few
+    /// cells changed since the last attempt to free them.
+    pub fn intern_hyperlink(&mut self, link: &Hyperlink) -> Option<HyperlinkId> {
+        self.hyperlinks.intern(link).or_else(|| {
+            self.reclaim_ids(None);
+            self.hyperlinks.intern(link)
+        })
+    }
+
+    /// Look up a hyperlink by id
+    ///
+    /// Works for cells in the visible area and the scrollback alike.
+    pub fn hyperlink(&self, id: HyperlinkId) -> Option<&Hyperlink> {
+        self.hyperlinks.get(id)
+    }
+
+    /// Get the hyperlink of the cell at a visible point, if any
+    pub fn hyperlink_at(&self, point: Point) -> Option<&Hyperlink> {
+        let cell = self.get(point.col, point.row)?;
+        self.hyperlink(self.cell_extra(cell)?.hyperlink?)
+    }
+
+    /// Intern out-of-line cell data in this grid's extra table
+    ///
+    /// When the table is full, entries no cell refers to anymore are freed
+    /// first. Returns None for empty data, if all entries are still in use,
+    /// or if too few cells changed since the last attempt to free them.
+    pub fn intern_extra(&mut self, extra: &CellExtra) -> Option<ExtraId> {
+        self.extras.intern(extra).or_else(|| {
+            // The link of the new entry may not be referenced by any cell yet
+            self.reclaim_ids(extra.hyperlink);
+            self.extras.intern(extra)
+        })
+    }
+
+    /// Free the table entries that no cell in the grid or the scrollback
+    /// refers to, except for the link `keep`
+    ///
+    /// Nothing is scanned until enough cells changed since the last scan.
+    fn reclaim_ids(&mut self, keep: Option<HyperlinkId>) {
+        if self.changed_cells < self.reclaim_after {
+            return;
+        }
+        let scanned = self.cells.len() + self.scrollback.iter().map(Vec::len).sum::<usize>();
+        self.changed_cells = 0;
+        self.reclaim_after = scanned / RECLAIM_SCAN_RATIO;
+
+        let live: HashSet<ExtraId> = self
+            .cells
+            .iter()
+            .chain(self.scrollback.iter().flatten())
+            .filter_map(|cell| cell.extra)
+            .collect();
+        self.extras.retain(|id| live.contains(&id));
+
+        let links: HashSet<HyperlinkId> =
+            live.iter().filter_map(|&id| self.extras.get(id)?.hyperlink).chain(keep).collect();
+        self.hyperlinks.retain(|id| links.contains(&id));
+    }
+
+    /// Look up out-of-line cell data by id
+    pub fn extra(&self, id: ExtraId) -> Option<&CellExtra> {
+        self.extras.get(id)
+    }
+
+    /// Get the out-of-line data of a cell, if it has any
+    pub fn cell_extra(&self, cell: &Cell) -> Option<&CellExtra> {
+        self.extra(cell.extra?)
+    }
+
+    /// Get the hyperlink id of a cell, if it has one
+    pub fn cell_hyperlink(&self, cell: &Cell) -> Option<HyperlinkId> {
+        self.cell_extra(cell)?.hyperlink
+    }
+
+    /// Get the zero-width characters that follow a cell's character
+    pub fn zerowidth(&self, cell: &Cell) -> &str {
+        self.cell_extra(cell).map_or("", |extra| &extra.zerowidth)
+    }
+
+    /// Attach a zero-width character to the cell at a visible position
+    ///
+    /// The character becomes part of the cell's grapheme cluster.
+    pub fn push_zerowidth(&mut self, col: usize, row: usize, c: char) {
+        let Some(cell) = self.get(col, row) else {
+            return;
+        };
+        let mut extra = self.cell_extra(cell).cloned().unwrap_or_default();
+        extra.push_zerowidth(c);
+        let id = self.intern_extra(&extra);
+        if let Some(cell) = self.get_mut(col, row) {
+            cell.extra = id;
+        }
+    }
+
+    /// Append the full grapheme cluster of a cell to a string
+    ///
+    /// Spacer cells (the right half of wide characters) add nothing.
+    pub fn push_cell_text(&self, text: &mut String, cell: &Cell) {
+        if !cell.flags.contains(CellFlags::WIDE_CHAR_SPACER) {
+            text.push(cell.c);
+            text.push_str(self.zerowidth(cell));
         }
     }
 
+    /// Set the background color used by background color erase (BCE)
+    ///
+    /// Like xterm, cells blanked by erase, scroll and insert operations only
+    /// take the current background color. The foreground, attributes such as
+    /// underline or inverse, and hyperlinks are not carried over, so an erased
+    /// area never shows decorations that nothing was printed with.
+    pub fn set_background(&mut self, bg: Color) {
+        self.background = bg;
+    }
+
+    /// Get the background color of erased cells
+    pub fn background(&self) -> Color {
+        self.background
+    }
+
+    /// Get the blank cell erase operations fill with
+    pub fn blank(&self) -> Cell {
+        Cell { bg: self.background, ..Cell::default() }
+    }
+
     /// Clear the entire grid
     pub fn clear(&mut self) {
-        for cell in &mut self.cells {
-            cell.reset();
+        let blank = self.blank();
+        self.cells.fill(blank);
+        self.changed_cells += self.cells.len();
+        for marks in &mut self.marks {
+            marks.clear();
+        }
+    }
+
+    /// Place a shell integration mark at a visible position
+    pub fn add_mark(&mut self, col: usize, row: usize, mark: SemanticMark) {
+        if let Some(marks) = self.marks.get_mut(row) {
+            marks.push(LineMark { col, mark });
         }
     }
 
+    /// Get the shell integration marks of a line
+    ///
+    /// Lines are numbered from the oldest scrollback line, so the visible
+    /// rows start at [`Grid::scrollback_len`].
+    pub fn line_marks(&self, line: usize) -> &[LineMark] {
+        match line.checked_sub(self.scrollback.len()) {
+            None => &self.scrollback_marks[line],
+            Some(row) => self.marks.get(row).map_or(&[], Vec::as_slice),
+        }
+    }
+
+    /// Get the commands recorded by shell integration, oldest first
+    ///
+    /// Points use absolute line numbers (see [`Grid::line_marks`]).
+    pub fn commands(&self) -> Vec<CommandRecord> {
+        let lines = self.scrollback_marks.iter().chain(&self.marks).map(Vec::as_slice);
+        semantic::collect_commands(lines.enumerate().filter(|(_, marks)| !marks.is_empty()))
+    }
+
+    /// Get the output of a command as text
+    ///
+    /// A command that is still running extends to the end of the grid.
+    pub fn command_output(&self, command: &CommandRecord) -> String {
+        let Some(start) = command.output else {
+            return String::new();
+        };
+        let end =
+            command.end.unwrap_or(Point::new(self.cols, self.scrollback.len() + self.rows - 1));
+        self.text_between(start, end)
+    }
+
+    /// Get the text between two points in absolute line numbers
+    ///
+    /// The end point is exclusive. Soft-wrapped rows are joined and trailing
+    /// blanks are trimmed from each line.
+    pub fn text_between(&self, start: Point, end: Point) -> String {
+        let mut lines: Vec<String> = Vec::new();
+        let mut continued = false;
+        for line in start.row..=end.row {
+            let cells = match line.checked_sub(self.scrollback.len()) {
+                None => &self.scrollback[line][..],
+                Some(row) if row < self.rows => &self.cells[row * self.cols..(row + 1) * self.cols],
+                Some(_) => break,
+            };
+            let from = if line == start.row { start.col.min(cells.len()) } else { 0 };
+            let to = if line == end.row { end.col.min(cells.len()) } else { cells.len() };
+            let mut text = String::new();
+            for cell in &cells[from..to.max(from)] {
+                self.push_cell_text(&mut text, cell);
+            }
+            match lines.last_mut() {
+                Some(last) if continued => last.push_str(&text),
+                _ => lines.push(text),
+            }
+
+            continued = to == cells.len() && is_wrapped(cells);
+            if !continued {
+                if let Some(last) = lines.last_mut() {
+                    last.truncate(last.trim_end().len());
+                }
+            }
+        }
+        // The end of the output is usually the start of an empty line
+        if end.col == 0 && lines.len() > 1 && lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+        lines.join("\n")
+    }
+
+    /// Erase a wide character overlapping the given cell
+    ///
+    /// If the cell is either half of a wide character, both halves are reset
+    /// so that no orphaned half is left behind when one of them is overwritten.
+    pub fn clear_wide_pair(&mut self, col: usize, row: usize) {
+        let Some(cell) = self.get(col, row) else {
+            return;
+        };
+        let start = if cell.flags.contains(CellFlags::WIDE_CHAR) {
+            col
+        } else if cell.flags.contains(CellFlags::WIDE_CHAR_SPACER)
+            && col > 0
+            && self.get(col - 1, row).is_some_and(|cell| cell.flags.contains(CellFlags::WIDE_CHAR))
+        {
+            col - 1
+        } else {
+            return;
+        };
+        let blank = self.blank();
+        for col in start..(start + 2).min(self.cols) {
+            if let Some(cell) = self.get_mut(col, row) {
+                *cell = blank;
+            }
+        }
+    }
+
+    /// Check if a visible row is soft-wrapped onto the next row
+    pub fn is_wrapped(&self, row: usize) -> bool {
+        row < self.rows && is_wrapped(&self.cells[row * self.cols..(row + 1) * self.cols])
+    }
+
     /// Clear a specific row
     pub fn clear_row(&mut self, row: usize) {
         if row >= self.rows {
             return;
         }
+        self.break_wide_chars(Rect::new(row, 0, row, self.cols.saturating_sub(1)));
         let start = row * self.cols;
         let end = start + self.cols;
-        for cell in &mut self.cells[start..end] {
-            cell.reset();
-        }
+        let blank = self.blank();
+        self.cells[start..end].fill(blank);
+        self.changed_cells += self.cols;
     }
 
     /// Clear from cursor to end of row
     pub fn clear_to_end_of_row(&mut self, col: usize, row: usize) {
-        if row >= self.rows {
+        if col >= self.cols || row >= self.rows {
             return;
         }
+        self.break_wide_chars(Rect::new(row, col, row, self.cols - 1));
         let start = row * self.cols + col;
         let end = (row + 1) * self.cols;
-        for cell in &mut self.cells[start..end] {
-            cell.reset();
+        let blank = self.blank();
+        self.cells[start..end].fill(blank);
+        self.changed_cells += end - start;
+    }
+
+    /// Insert n blank cells at the given position, shifting the cells up to
+    /// the `right` margin (inclusive) right
+    ///
+    /// Cells shifted past the right margin are discarded.
+    pub fn insert_cells(&mut self, col: usize, row: usize, right: usize, n: usize) {
+        let right = right.min(self.cols.saturating_sub(1));
+        if col > right || row >= self.rows {
+            return;
+        }
+        self.break_wide_chars(Rect::new(row, col, row, right));
+        let n = n.min(right + 1 - col);
+        let start = row * self.cols + col;
+        let end = row * self.cols + right + 1;
+
+        self.cells.copy_within(start..end - n, start + n);
+        let blank = self.blank();
+        self.cells[start..start + n].fill(blank);
+        // A wide character shifted onto the margin has lost its spacer
+        if self.cells[end - 1].flags.contains(CellFlags::WIDE_CHAR) {
+            self.cells[end - 1] = blank;
         }
     }
 
+    /// Delete n cells at the given position, shifting the cells up to the
+    /// `right` margin (inclusive) left
+    ///
+    /// Blank cells are inserted at the right margin.
+    pub fn delete_cells(&mut self, col: usize, row: usize, right: usize, n: usize) {
+        let right = right.min(self.cols.saturating_sub(1));
+        if col > right || row >= self.rows {
+            return;
+        }
+        self.break_wide_chars(Rect::new(row, col, row, right));
+        let n = n.min(right + 1 - col);
+        let start = row * self.cols + col;
+        let end = row * self.cols + right + 1;
+
+        self.cells.copy_within(start + n..end, start);
+        let blank = self.blank();
+        self.cells[end - n..end].fill(blank);
+        // The first half of a wide character may have been deleted
+        if self.cells[start].flags.contains(CellFlags::WIDE_CHAR_SPACER) {
+            self.cells[start] = blank;
+        }
+    }
+
+    /// Erase n cells starting at the given position without shifting
+    pub fn erase_cells(&mut self, col: usize, row: usize, n: usize) {
+        if col >= self.cols || row >= self.rows {
+            return;
+        }
+        let n = n.min(self.cols - col);
+        if n == 0 {
+            return;
+        }
+        self.break_wide_chars(Rect::new(row, col, row, col + n - 1));
+        let start = row * self.cols + col;
+        let blank = self.blank();
+        self.cells[start..start + n].fill(blank);
+        self.changed_cells += n;
+    }
+
+    /// Insert n blank lines at `row`, pushing lines down towards `bottom`
+    ///
+    /// Lines pushed past the bottom margin are discarded.
+    pub fn insert_lines(&mut self, row: usize, bottom: usize, n: usize) {
+        self.scroll_region_down(row, bottom, n);
+    }
+
+    /// Delete n lines at `row`, pulling lines up from `bottom`
+    ///
+    /// Blank lines are inserted at the bottom margin and deleted lines never
+    /// reach the scrollback.
+    pub fn delete_lines(&mut self, row: usize, bottom: usize, n: usize) {
+        self.scroll_region_up(row, bottom, n);
+    }
+
     /// Scroll up by n lines (lines move to scrollback)
     pub fn scroll_up(&mut self, n: usize) {
-        for _ in 0..n {
-            // Take the top row and add it to scrollback
-            let mut row = Vec::with_capacity(self.cols);
-            for col in 0..self.cols {
-                let index = col;
-                row.push(self.cells[index]);
-            }
+        let n = n.min(self.rows);
+        for row in 0..n {
+            // Take the top rows and add them to scrollback
+            let start = row * self.cols;
+            self.scrollback.push_back(self.cells[start..start + self.cols].to_vec());
+            self.scrollback_marks.push_back(std::mem::take(&mut self.marks[row]));
 
-            // Add to scrollback (limit size)
-            self.scrollback.push_back(row);
+            // Limit scrollback size
             if self.scrollback.len() > self.max_scrollback {
-                self.scrollback.pop_front();
+                let dropped = self.scrollback.pop_front();
+                self.changed_cells += dropped.map_or(0, |line| line.len());
+                self.scrollback_marks.pop_front();
             }
+        }
+
+        self.scroll_region_up(0, self.rows - 1, n);
+    }
+
+    /// Scroll down by n lines (insert blank lines at top)
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll_region_down(0, self.rows - 1, n);
+    }
 
-            // Shift all rows up
-            let cells_len = self.cells.len();
-            self.cells.copy_within(self.cols..cells_len, 0);
+    /// Scroll the lines between `top` and `bottom` (inclusive) up by n lines
+    ///
+    /// Lines scrolled out of the region are discarded, never pushed into
+    /// the scrollback, and blank lines are inserted at the bottom margin.
+    pub fn scroll_region_up(&mut self, top: usize, bottom: usize, n: usize) {
+        if top > bottom || bottom >= self.rows {
+            return;
+        }
+        let n = n.min(bottom - top + 1);
+        let start = top * self.cols;
+        let end = (bottom + 1) * self.cols;
+        let shift = n * self.cols;
 
-            // Clear the last row
-            let last_row_start = (self.rows - 1) * self.cols;
-            for i in last_row_start..cells_len {
-                self.cells[i].reset();
+        self.cells.copy_within(start + shift..end, start);
+        let blank = self.blank();
+        self.cells[end - shift..end].fill(blank);
+        self.changed_cells += shift;
+        self.marks[top..=bottom].rotate_left(n);
+        for marks in &mut self.marks[bottom + 1 - n..=bottom] {
+            marks.clear();
+        }
+    }
+
+    /// Scroll the lines between `top` and `bottom` (inclusive) down by n lines
+    ///
+    /// Lines pushed past the bottom margin are discarded and blank lines are
+    /// inserted at the top margin.
+    pub fn scroll_region_down(&mut self, top: usize, bottom: usize, n: usize) {
+        if top > bottom || bottom >= self.rows {
+            return;
+        }
+        let n = n.min(bottom - top + 1);
+        let start = top * self.cols;
+        let end = (bottom + 1) * self.cols;
+        let shift = n * self.cols;
+
+        self.cells.copy_within(start..end - shift, start + shift);
+        let blank = self.blank();
+        self.cells[start..start + shift].fill(blank);
+        self.changed_cells += shift;
+        self.marks[top..=bottom].rotate_right(n);
+        for marks in &mut self.marks[top..top + n] {
+            marks.clear();
+        }
+    }
+
+    /// Clip a rectangle to the visible grid
+    ///
+    /// Returns None if nothing of it is visible.
+    fn clip_rect(&self, rect: Rect) -> Option<Rect> {
+        let rect = Rect {
+            bottom: rect.bottom.min(self.rows.checked_sub(1)?),
+            right: rect.right.min(self.cols.checked_sub(1)?),
+            ..rect
+        };
+        (rect.top <= rect.bottom && rect.left <= rect.right).then_some(rect)
+    }
+
+    /// Erase wide characters that straddle the left or right edge of a
+    /// rectangle, so that an operation on it cannot split them
+    fn break_wide_chars(&mut self, rect: Rect) {
+        for row in rect.top..=rect.bottom {
+            let has_flag =
+                |grid: &Self, col, flag| grid.get(col, row).is_some_and(|c| c.flags.contains(flag));
+            if has_flag(self, rect.left, CellFlags::WIDE_CHAR_SPACER) {
+                self.clear_wide_pair(rect.left, row);
+            }
+            if has_flag(self, rect.right, CellFlags::WIDE_CHAR) {
+                self.clear_wide_pair(rect.right, row);
             }
         }
     }
 
-    /// Scroll down by n lines (insert blank lines at top)
-    pub fn scroll_down(&mut self, n: usize) {
-        for _ in 0..n {
-            // Shift all rows down
-            let last_row_start = (self.rows - 1) * self.cols;
-            self.cells.copy_within(0..last_row_start, self.cols);
-
-            // Clear the first row
-            for i in 0..self.cols {
-                self.cells[i].reset();
+    /// Fill a rectangle with copies of a cell (DECFRA)
+    pub fn fill_rect(&mut self, rect: Rect, cell: Cell) {
+        let Some(rect) = self.clip_rect(rect) else {
+            return;
+        };
+        self.break_wide_chars(rect);
+        for row in rect.top..=rect.bottom {
+            let start = row * self.cols;
+            self.cells[start + rect.left..=start + rect.right].fill(cell);
+        }
+        self.changed_cells += (rect.bottom + 1 - rect.top) * (rect.right + 1 - rect.left);
+    }
+
+    /// Erase a rectangle (DECERA)
+    pub fn erase_rect(&mut self, rect: Rect) {
+        self.fill_rect(rect, self.blank());
+    }
+
+    /// Erase the cells of a rectangle that are not protected by DECSCA
+    /// (DECSERA)
+    pub fn selective_erase_rect(&mut self, rect: Rect) {
+        let Some(rect) = self.clip_rect(rect) else {
+            return;
+        };
+        let blank = self.blank();
+        for row in rect.top..=rect.bottom {
+            for col in rect.left..=rect.right {
+                let cell = self.cells[row * self.cols + col];
+                if self.cell_extra(&cell).is_some_and(|extra| extra.protected) {
+                    continue;
+                }
+                // Only unprotected wide characters may be broken up
+                if col == rect.left || col == rect.right {
+                    self.clear_wide_pair(col, row);
+                }
+                self.cells[row * self.cols + col] = blank;
+            }
+        }
+    }
+
+    /// Copy a rectangle so that its top left corner lands at `(left, top)`
+    /// (DECCRA)
+    ///
+    /// The source and destination may overlap. Parts of the destination
+    /// outside the grid are dropped.
+    pub fn copy_rect(&mut self, src: Rect, top: usize, left: usize) {
+        let Some(src) = self.clip_rect(src) else {
+            return;
+        };
+        let dst = Rect::new(top, left, top + (src.bottom - src.top), left + (src.right - src.left));
+        let Some(dst) = self.clip_rect(dst) else {
+            return;
+        };
+        let width = dst.right - dst.left + 1;
+        let rows: Vec<Vec<Cell>> = (0..=dst.bottom - dst.top)
+            .map(|offset| {
+                let start = (src.top + offset) * self.cols + src.left;
+                self.cells[start..start + width].to_vec()
+            })
+            .collect();
+
+        self.break_wide_chars(dst);
+        let blank = self.blank();
+        for (offset, cells) in rows.into_iter().enumerate() {
+            let start = (dst.top + offset) * self.cols + dst.left;
+            let row = &mut self.cells[start..start + width];
+            row.copy_from_slice(&cells);
+            // Wide characters cut by the source rectangle are dropped
+            if row[0].flags.contains(CellFlags::WIDE_CHAR_SPACER) {
+                row[0] = blank;
+            }
+            if row[width - 1].flags.contains(CellFlags::WIDE_CHAR) {
+                row[width - 1] = blank;
+            }
+        }
+    }
+
+    /// Change the attributes of every cell in a rectangle (DECCARA)
+    ///
+    /// The flags in `clear` are removed before the flags in `set` are added.
+    pub fn change_attributes(&mut self, rect: Rect, set: CellFlags, clear: CellFlags) {
+        let Some(rect) = self.clip_rect(rect) else {
+            return;
+        };
+        for row in rect.top..=rect.bottom {
+            let start = row * self.cols;
+            for cell in &mut self.cells[start + rect.left..=start + rect.right] {
+                cell.flags = cell.flags.difference(clear).union(set);
             }
         }
     }
 
+    /// Scroll the contents of a rectangle up by n lines
+    ///
+    /// Used when left and right margins are set: lines never reach the
+    /// scrollback and blank lines are inserted at the bottom.
+    pub fn scroll_rect_up(&mut self, rect: Rect, n: usize) {
+        let Some(rect) = self.clip_rect(rect) else {
+            return;
+        };
+        let height = rect.bottom - rect.top + 1;
+        if n >= height {
+            self.erase_rect(rect);
+            return;
+        }
+        if n == 0 {
+            return;
+        }
+        self.break_wide_chars(rect);
+        for row in rect.top..=rect.bottom - n {
+            let src = (row + n) * self.cols + rect.left;
+            self.cells.copy_within(src..=src + rect.right - rect.left, row * self.cols + rect.left);
+        }
+        self.erase_rect(Rect { top: rect.bottom + 1 - n, ..rect });
+    }
+
+    /// Scroll the contents of a rectangle down by n lines
+    ///
+    /// Blank lines are inserted at the top.
+    pub fn scroll_rect_down(&mut self, rect: Rect, n: usize) {
+        let Some(rect) = self.clip_rect(rect) else {
+            return;
+        };
+        let height = rect.bottom - rect.top + 1;
+        if n >= height {
+            self.erase_rect(rect);
+            return;
+        }
+        if n == 0 {
+            return;
+        }
+        self.break_wide_chars(rect);
+        for row in (rect.top + n..=rect.bottom).rev() {
+            let src = (row - n) * self.cols + rect.left;
+            self.cells.copy_within(src..=src + rect.right - rect.left, row * self.cols + rect.left);
+        }
+        self.erase_rect(Rect { bottom: rect.top + n - 1, ..rect });
+    }
+
     /// Resize the grid
     pub fn resize(&mut self, cols: usize, rows: usize) {
         if cols == self.cols && rows == self.rows {
@@ -168,11 +736,18 @@ impl Grid {
                 let new_index = row * cols + col;
                 new_cells[new_index] = self.cells[old_index];
             }
+            // A wide character cut off at the new right edge lost its spacer
+            if let Some(last) = new_cells[row * cols..row * cols + min_cols].last_mut() {
+                if last.flags.contains(CellFlags::WIDE_CHAR) {
+                    *last = Cell::default();
+                }
+            }
         }
 
         self.cols = cols;
         self.rows = rows;
         self.cells = new_cells;
+        self.marks.resize(rows, Vec::new());
     }
 
     /// Get a line from scrollback (0 = oldest)
@@ -182,10 +757,17 @@ impl Grid {
 
     /// Clear scrollback buffer
     pub fn clear_scrollback(&mut self) {
+        self.changed_cells += self.scrollback.iter().map(Vec::len).sum::<usize>();
         self.scrollback.clear();
+        self.scrollback_marks.clear();
     }
 }
 
+/// Check if a row of cells continues on the next row
+fn is_wrapped(cells: &[Cell]) -> bool {
+    cells.last().is_some_and(|cell| cell.flags.contains(CellFlags::WRAPLINE))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -202,7 +784,7 @@ mod tests {
     fn test_grid_get_set() {
         let mut grid = Grid::new(80, 24, 10000);
         let cell = Cell::new('A');
-        grid.set(5, 10, cell.clone());
+        grid.set(5, 10, cell);
         assert_eq!(grid.get(5, 10), Some(&cell));
     }
 
@@ -232,6 +814,442 @@ mod tests {
         assert!(grid.get(0, 0).unwrap().is_empty());
     }
 
+    #[test]
+    fn test_grid_scroll_down() {
+        let mut grid = Grid::new(10, 3, 100);
+        grid.set(0, 0, Cell::new('A'));
+        grid.set(0, 2, Cell::new('C'));
+        grid.scroll_down(1);
+        assert!(grid.get(0, 0).unwrap().is_empty());
+        assert_eq!(grid.get(0, 1).unwrap().c, 'A');
+        assert_eq!(grid.scrollback_len(), 0);
+    }
+
+    #[test]
+    fn test_grid_scroll_region_up() {
+        let mut grid = Grid::new(10, 5, 100);
+        for (row, c) in "ABCDE".chars().enumerate() {
+            grid.set(0, row, Cell::new(c));
+        }
+        grid.scroll_region_up(1, 3, 1);
+
+        let column: String = (0..5).map(|row| grid.get(0, row).unwrap().c).collect();
+        assert_eq!(column, "ACD E");
+        // Partial regions never feed the scrollback
+        assert_eq!(grid.scrollback_len(), 0);
+    }
+
+    #[test]
+    fn test_grid_scroll_region_down() {
+        let mut grid = Grid::new(10, 5, 100);
+        for (row, c) in "ABCDE".chars().enumerate() {
+            grid.set(0, row, Cell::new(c));
+        }
+        grid.scroll_region_down(1, 3, 2);
+
+        let column: String = (0..5).map(|row| grid.get(0, row).unwrap().c).collect();
+        assert_eq!(column, "A  BE");
+    }
+
+    #[test]
+    fn test_grid_scroll_region_clamps_count() {
+        let mut grid = Grid::new(10, 5, 100);
+        for (row, c) in "ABCDE".chars().enumerate() {
+            grid.set(0, row, Cell::new(c));
+        }
+        grid.scroll_region_up(1, 3, 10);
+
+        let column: String = (0..5).map(|row| grid.get(0, row).unwrap().c).collect();
+        assert_eq!(column, "A   E");
+    }
+
+    /// Collect a row as a string
+    fn row_text(grid: &Grid, row: usize) -> String {
+        (0..grid.cols()).map(|col| grid.get(col, row).unwrap().c).collect()
+    }
+
+    /// Fill a row from a string
+    fn fill_row(grid: &mut Grid, row: usize, text: &str) {
+        for (col, c) in text.chars().enumerate() {
+            grid.set(col, row, Cell::new(c));
+        }
+    }
+
+    #[test]
+    fn test_grid_insert_cells() {
+        let mut grid = Grid::new(6, 1, 0);
+        fill_row(&mut grid, 0, "ABCDEF");
+        grid.insert_cells(1, 0, 5, 2);
+        assert_eq!(row_text(&grid, 0), "A  BCD");
+
+        grid.insert_cells(4, 0, 5, 10);
+        assert_eq!(row_text(&grid, 0), "A  B  ");
+    }
+
+    #[test]
+    fn test_grid_insert_cells_drops_split_wide_char() {
+        let mut grid = Grid::new(4, 1, 0);
+        fill_row(&mut grid, 0, "ab");
+        grid.set(2, 0, Cell { flags: CellFlags::WIDE_CHAR, ..Cell::new('中') });
+        grid.set(3, 0, Cell { flags: CellFlags::WIDE_CHAR_SPACER, ..Cell::default() });
+        grid.insert_cells(0, 0, 3, 1);
+        assert_eq!(row_text(&grid, 0), " ab ");
+        assert!(grid.get(3, 0).unwrap().flags.is_empty());
+    }
+
+    #[test]
+    fn test_grid_delete_cells() {
+        let mut grid = Grid::new(6, 1, 0);
+        fill_row(&mut grid, 0, "ABCDEF");
+        grid.delete_cells(1, 0, 5, 2);
+        assert_eq!(row_text(&grid, 0), "ADEF  ");
+
+        grid.delete_cells(2, 0, 5, 10);
+        assert_eq!(row_text(&grid, 0), "AD    ");
+    }
+
+    #[test]
+    fn test_grid_insert_delete_cells_within_margin() {
+        let mut grid = Grid::new(6, 1, 0);
+        fill_row(&mut grid, 0, "ABCDEF");
+        grid.insert_cells(1, 0, 3, 1);
+        assert_eq!(row_text(&grid, 0), "A BCEF");
+
+        grid.delete_cells(0, 0, 2, 1);
+        assert_eq!(row_text(&grid, 0), " B CEF");
+    }
+
+    #[test]
+    fn test_grid_delete_cells_drops_split_wide_char() {
+        let mut grid = Grid::new(4, 1, 0);
+        grid.set(0, 0, Cell { flags: CellFlags::WIDE_CHAR, ..Cell::new('中') });
+        grid.set(1, 0, Cell { flags: CellFlags::WIDE_CHAR_SPACER, ..Cell::default() });
+        grid.set(2, 0, Cell::new('a'));
+        grid.delete_cells(0, 0, 3, 1);
+        assert_eq!(row_text(&grid, 0), " a  ");
+        assert!(grid.get(0, 0).unwrap().flags.is_empty());
+    }
+
+    #[test]
+    fn test_grid_scroll_rect() {
+        let mut grid = Grid::new(4, 3, 100);
+        for (row, text) in ["ABCD", "EFGH", "IJKL"].iter().enumerate() {
+            fill_row(&mut grid, row, text);
+        }
+        grid.scroll_rect_up(Rect::new(0, 1, 2, 2), 1);
+        assert_eq!(row_text(&grid, 0), "AFGD");
+        assert_eq!(row_text(&grid, 1), "EJKH");
+        assert_eq!(row_text(&grid, 2), "I  L");
+        assert_eq!(grid.scrollback_len(), 0);
+
+        grid.scroll_rect_down(Rect::new(0, 1, 2, 2), 2);
+        assert_eq!(row_text(&grid, 0), "A  D");
+        assert_eq!(row_text(&grid, 1), "E  H");
+        assert_eq!(row_text(&grid, 2), "IFGL");
+    }
+
+    #[test]
+    fn test_grid_copy_rect_overlapping() {
+        let mut grid = Grid::new(5, 2, 0);
+        fill_row(&mut grid, 0, "ABCDE");
+        grid.copy_rect(Rect::new(0, 0, 0, 2), 0, 1);
+        assert_eq!(row_text(&grid, 0), "AABCE");
+
+        // The destination is clipped to the grid
+        grid.copy_rect(Rect::new(0, 0, 0, 4), 1, 3);
+        assert_eq!(row_text(&grid, 1), "   AA");
+    }
+
+    #[test]
+    fn test_grid_fill_and_erase_rect() {
+        let mut grid = Grid::new(4, 3, 0);
+        grid.fill_rect(Rect::new(0, 1, 1, 2), Cell::new('x'));
+        assert_eq!(row_text(&grid, 0), " xx ");
+        assert_eq!(row_text(&grid, 1), " xx ");
+        assert_eq!(row_text(&grid, 2), "    ");
+
+        grid.erase_rect(Rect::new(1, 0, 5, 1));
+        assert_eq!(row_text(&grid, 1), "  x ");
+    }
+
+    #[test]
+    fn test_grid_selective_erase_keeps_protected_cells() {
+        let mut grid = Grid::new(4, 1, 0);
+        let protected = CellExtra { protected: true, ..CellExtra::default() };
+        let extra = grid.intern_extra(&protected);
+        fill_row(&mut grid, 0, "abcd");
+        grid.set(1, 0, Cell { extra, ..Cell::new('P') });
+        grid.selective_erase_rect(Rect::new(0, 0, 0, 3));
+        assert_eq!(row_text(&grid, 0), " P  ");
+    }
+
+    #[test]
+    fn test_grid_change_attributes() {
+        let mut grid = Grid::new(3, 1, 0);
+        grid.set(0, 0, Cell { flags: CellFlags::ITALIC | CellFlags::BOLD, ..Cell::new('a') });
+        grid.change_attributes(Rect::new(0, 0, 0, 1), CellFlags::INVERSE, CellFlags::BOLD);
+        assert_eq!(grid.get(0, 0).unwrap().flags, CellFlags::ITALIC | CellFlags::INVERSE);
+        assert_eq!(grid.get(1, 0).unwrap().flags, CellFlags::INVERSE);
+        assert!(grid.get(2, 0).unwrap().flags.is_empty());
+    }
+
+    #[test]
+    fn test_grid_erase_cells() {
+        let mut grid = Grid::new(6, 1, 0);
+        fill_row(&mut grid, 0, "ABCDEF");
+        grid.erase_cells(2, 0, 3);
+        assert_eq!(row_text(&grid, 0), "AB   F");
+
+        grid.erase_cells(4, 0, 10);
+        assert_eq!(row_text(&grid, 0), "AB    ");
+    }
+
+    #[test]
+    fn test_grid_erase_uses_background() {
+        let mut grid = Grid::new(4, 3, 100);
+        let bg = Color::Indexed(4);
+        grid.set_background(bg);
+        grid.clear_row(0);
+        grid.insert_cells(0, 1, 3, 1);
+        grid.scroll_up(1);
+
+        assert!(grid.scrollback_line(0).unwrap().iter().all(|cell| cell.bg == bg));
+        assert_eq!(grid.get(0, 0).unwrap().bg, bg);
+        assert_eq!(grid.get(0, 0).unwrap().fg, Color::default());
+        assert_eq!(grid.get(1, 0).unwrap().bg, Color::Named(NamedColor::Background));
+        assert!((0..4).all(|col| grid.get(col, 2).unwrap().bg == bg));
+    }
+
+    #[test]
+    fn test_grid_insert_delete_lines() {
+        let mut grid = Grid::new(1, 5, 100);
+        for (row, c) in "ABCDE".chars().enumerate() {
+            grid.set(0, row, Cell::new(c));
+        }
+        grid.insert_lines(1, 3, 1);
+        let column: String = (0..5).map(|row| grid.get(0, row).unwrap().c).collect();
+        assert_eq!(column, "A BCE");
+
+        grid.delete_lines(0, 4, 2);
+        let column: String = (0..5).map(|row| grid.get(0, row).unwrap().c).collect();
+        assert_eq!(column, "BCE  ");
+        assert_eq!(grid.scrollback_len(), 0);
+    }
+
+    #[test]
+    fn test_grid_hyperlink_lookup() {
+        let mut grid = Grid::new(10, 2, 100);
+        let link = Hyperlink::new(None, "https://example.com");
+        let id = grid.intern_hyperlink(&link).unwrap();
+        let extra = grid.intern_extra(&CellExtra { hyperlink: Some(id), ..CellExtra::default() });
+        grid.set(3, 0, Cell { extra, ..Cell::new('x') });
+
+        assert_eq!(grid.hyperlink_at(Point::new(3, 0)), Some(&link));
+        assert_eq!(grid.hyperlink_at(Point::new(4, 0)), None);
+        assert_eq!(grid.hyperlink_at(Point::new(30, 0)), None);
+    }
+
+    #[test]
+    fn test_grid_hyperlink_survives_scrollback() {
+        let mut grid = Grid::new(10, 2, 100);
+        let link = Hyperlink::new(Some("a".into()), "file:///etc/hosts");
+        let id = grid.intern_hyperlink(&link).unwrap();
+        let extra = grid.intern_extra(&CellExtra { hyperlink: Some(id), ..CellExtra::default() });
+        grid.set(0, 0, Cell { extra, ..Cell::new('x') });
+        grid.scroll_up(1);
+
+        let cell = grid.scrollback_line(0).unwrap()[0];
+        assert_eq!(grid.hyperlink(grid.cell_hyperlink(&cell).unwrap()), Some(&link));
+    }
+
+    /// Fill every cell but one of a 256x256 grid with its own extra entry,
+    /// leaving the 65535-entry table full of live ids
+    fn fill_extras(grid: &mut Grid, extra: impl Fn(usize) -> CellExtra) {
+        for i in 0..u16::MAX as usize {
+            let id = grid.intern_extra(&extra(i));
+            assert!(id.is_some());
+            grid.set(i % 256, i / 256, Cell { extra: id, ..Cell::new('x') });
+        }
+    }
+
+    #[test]
+    fn test_grid_full_extra_table_backs_off() {
+        let mut grid = Grid::new(256, 256, 0);
+        fill_extras(&mut grid, |i| CellExtra { zerowidth: i.to_string(), ..CellExtra::default() });
+        let new = CellExtra { zerowidth: "new".into(), ..CellExtra::default() };
+        assert_eq!(grid.intern_extra(&new), None);
+
+        // One overwritten cell is not worth scanning the grid again for
+        grid.set(0, 0, Cell::default());
+        assert_eq!(grid.intern_extra(&new), None);
+
+        for i in 1..RECLAIM_SCAN_RATIO * 256 {
+            grid.set(i % 256, i / 256, Cell::default());
+        }
+        assert!(grid.intern_extra(&new).is_some());
+    }
+
+    #[test]
+    fn test_grid_full_hyperlink_table_backs_off() {
+        let mut grid = Grid::new(256, 256, 0);
+        for i in 0..u16::MAX as usize {
+            let link = Hyperlink::new(None, format!("https://example.com/{}", i));
+            let hyperlink = grid.intern_hyperlink(&link);
+            assert!(hyperlink.is_some());
+            let extra = grid.intern_extra(&CellExtra { hyperlink, ..CellExtra::default() });
+            grid.set(i % 256, i / 256, Cell { extra, ..Cell::new('x') });
+        }
+        let new = Hyperlink::new(None, "https://example.com/new");
+        assert_eq!(grid.intern_hyperlink(&new), None);
+
+        grid.set(0, 0, Cell::default());
+        assert_eq!(grid.intern_hyperlink(&new), None);
+
+        for i in 1..RECLAIM_SCAN_RATIO * 256 {
+            grid.set(i % 256, i / 256, Cell::default());
+        }
+        assert!(grid.intern_hyperlink(&new).is_some());
+    }
+
+    #[test]
+    fn test_grid_zerowidth_keeps_hyperlink() {
+        let mut grid = Grid::new(10, 2, 100);
+        let link = Hyperlink::new(None, "https://example.com");
+        let id = grid.intern_hyperlink(&link).unwrap();
+        let extra = grid.intern_extra(&CellExtra { hyperlink: Some(id), ..CellExtra::default() });
+        grid.set(0, 0, Cell { extra, ..Cell::new('a') });
+        grid.push_zerowidth(0, 0, '\u{308}');
+        grid.push_zerowidth(0, 0, '\u{323}');
+
+        let cell = *grid.get(0, 0).unwrap();
+        assert_eq!(grid.zerowidth(&cell), "\u{308}\u{323}");
+        assert_eq!(grid.hyperlink_at(Point::new(0, 0)), Some(&link));
+        assert_eq!(grid.text_between(Point::new(0, 0), Point::new(10, 0)), "a\u{308}\u{323}");
+
+        // Overwriting the cell drops its combining characters
+        grid.set(0, 0, Cell::new('b'));
+        assert_eq!(grid.zerowidth(grid.get(0, 0).unwrap()), "");
+    }
+
+    #[test]
+    fn test_grid_marks_follow_lines_into_scrollback() {
+        let mut grid = Grid::new(10, 3, 100);
+        fill_row(&mut grid, 1, "$ ls");
+        grid.add_mark(0, 1, SemanticMark::PromptStart);
+
+        grid.scroll_up(2);
+        assert_eq!(grid.scrollback_len(), 2);
+        assert_eq!(grid.line_marks(1), &[LineMark { col: 0, mark: SemanticMark::PromptStart }]);
+        assert!(grid.line_marks(2).is_empty());
+        assert_eq!(grid.commands()[0].prompt, Point::new(0, 1));
+    }
+
+    #[test]
+    fn test_grid_marks_follow_region_scrolls() {
+        let mut grid = Grid::new(10, 4, 0);
+        grid.add_mark(0, 2, SemanticMark::OutputStart);
+        grid.scroll_region_up(1, 3, 1);
+        assert_eq!(grid.line_marks(1).len(), 1);
+        grid.scroll_region_down(0, 3, 2);
+        assert_eq!(grid.line_marks(3).len(), 1);
+        assert!(grid.line_marks(1).is_empty());
+
+        grid.clear();
+        assert!(grid.line_marks(3).is_empty());
+    }
+
+    #[test]
+    fn test_grid_command_output() {
+        let mut grid = Grid::new(10, 5, 100);
+        fill_row(&mut grid, 0, "$ ls");
+        fill_row(&mut grid, 1, "a.txt");
+        fill_row(&mut grid, 2, "b.txt");
+        fill_row(&mut grid, 3, "$");
+        grid.add_mark(0, 0, SemanticMark::PromptStart);
+        grid.add_mark(2, 0, SemanticMark::CommandStart);
+        grid.add_mark(0, 1, SemanticMark::OutputStart);
+        grid.add_mark(0, 3, SemanticMark::CommandEnd(Some(0)));
+        grid.add_mark(0, 3, SemanticMark::PromptStart);
+        grid.scroll_up(1);
+
+        let commands = grid.commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].exit_code, Some(0));
+        assert_eq!(grid.command_output(&commands[0]), "a.txt\nb.txt");
+        assert_eq!(grid.command_output(&commands[1]), "");
+    }
+
+    #[test]
+    fn test_grid_text_between_joins_wrapped_rows() {
+        let mut grid = Grid::new(4, 3, 100);
+        fill_row(&mut grid, 0, "ab c");
+        fill_row(&mut grid, 1, "d");
+        fill_row(&mut grid, 2, "e");
+        grid.get_mut(3, 0).unwrap().flags.insert(CellFlags::WRAPLINE);
+        grid.scroll_up(1);
+
+        assert!(!grid.is_wrapped(0));
+        assert_eq!(grid.text_between(Point::new(0, 0), Point::new(4, 2)), "ab cd\ne");
+    }
+
+    #[test]
+    fn test_grid_clear_wide_pair() {
+        let mut grid = Grid::new(4, 1, 0);
+        grid.set(1, 0, Cell { flags: CellFlags::WIDE_CHAR, ..Cell::new('中') });
+        grid.set(2, 0, Cell { flags: CellFlags::WIDE_CHAR_SPACER, ..Cell::default() });
+        let before = grid.clone();
+
+        grid.clear_wide_pair(2, 0);
+        assert!(grid.get(1, 0).unwrap().is_empty());
+        assert!(grid.get(2, 0).unwrap().is_empty());
+
+        // Narrow cells are left alone
+        let mut grid = before;
+        grid.set(0, 0, Cell::new('a'));
+        grid.clear_wide_pair(0, 0);
+        assert_eq!(grid.get(0, 0).unwrap().c, 'a');
+        assert_eq!(grid.get(1, 0).unwrap().c, '中');
+    }
+
+    #[test]
+    fn test_grid_erase_splitting_wide_char() {
+        let wide_row = || {
+            let mut grid = Grid::new(4, 1, 0);
+            fill_row(&mut grid, 0, "a");
+            grid.set(1, 0, Cell { flags: CellFlags::WIDE_CHAR, ..Cell::new('中') });
+            grid.set(2, 0, Cell { flags: CellFlags::WIDE_CHAR_SPACER, ..Cell::default() });
+            grid.set(3, 0, Cell::new('b'));
+            grid
+        };
+        let is_plain = |grid: &Grid| (0..4).all(|col| grid.get(col, 0).unwrap().flags.is_empty());
+
+        // Erasing the spacer erases the whole character
+        let mut grid = wide_row();
+        grid.erase_cells(2, 0, 1);
+        assert_eq!(row_text(&grid, 0), "a  b");
+        assert!(is_plain(&grid));
+
+        let mut grid = wide_row();
+        grid.clear_to_end_of_row(2, 0);
+        assert_eq!(row_text(&grid, 0), "a   ");
+        assert!(is_plain(&grid));
+
+        // So does erasing the first half, which leaves no orphaned spacer
+        let mut grid = wide_row();
+        grid.erase_cells(0, 0, 2);
+        assert_eq!(row_text(&grid, 0), "   b");
+        assert!(is_plain(&grid));
+    }
+
+    #[test]
+    fn test_grid_resize_drops_cut_wide_char() {
+        let mut grid = Grid::new(4, 1, 0);
+        grid.set(2, 0, Cell { flags: CellFlags::WIDE_CHAR, ..Cell::new('中') });
+        grid.set(3, 0, Cell { flags: CellFlags::WIDE_CHAR_SPACER, ..Cell::default() });
+        grid.resize(3, 1);
+        assert!(grid.get(2, 0).unwrap().is_empty());
+    }
+
     #[test]
     fn test_grid_resize() {
         let mut grid = Grid::new(80, 24, 10000);
@@ -0,0 +1,164 @@
+//! OSC 8 hyperlink storage
+//!
+//! Hyperlinks are interned in a per-grid table so that each cell only
+//! carries a small [`HyperlinkId`] instead of the full URI. Cells moved into
+//! the scrollback keep their ids, so links stay resolvable there too. Ids
+//! of links no cell refers to anymore can be freed and are then reused.
+
+use std::collections::HashMap;
+use std::num::NonZeroU16;
+use tracing::debug;
+
+/// A hyperlink attached to cells with OSC 8
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Hyperlink {
+    /// Optional `id=` parameter used to group cells of one link
+    pub id: Option<String>,
+    /// Target URI
+    pub uri: String,
+}
+
+impl Hyperlink {
+    /// Create a new hyperlink
+    pub fn new(id: Option<String>, uri: impl Into<String>) -> Self {
+        Self { id, uri: uri.into() }
+    }
+}
+
+/// Handle to a hyperlink interned in a [`HyperlinkTable`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HyperlinkId(NonZeroU16);
+
+impl HyperlinkId {
+    /// Get the index of the link in the table
+    fn index(self) -> usize {
+        self.0.get() as usize - 1
+    }
+}
+
+/// Interned hyperlinks referenced by grid cells
+#[derive(Clone, Debug, Default)]
+pub struct HyperlinkTable {
+    /// Hyperlinks, indexed by id - 1, with None for freed ids
+    links: Vec<Option<Hyperlink>>,
+    /// Reverse lookup for interning
+    ids: HashMap<Hyperlink, HyperlinkId>,
+    /// Freed ids, reused before new ones are handed out
+    free: Vec<HyperlinkId>,
+}
+
+impl HyperlinkTable {
+    /// Create an empty hyperlink table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Intern a hyperlink, returning its id
+    ///
+    /// Returns None if the table is full.
+    pub fn intern(&mut self, link: &Hyperlink) -> Option<HyperlinkId> {
+        if let Some(&id) = self.ids.get(link) {
+            return Some(id);
+        }
+
+        let id = if let Some(id) = self.free.pop() {
+            self.links[id.index()] = Some(link.clone());
+            id
+        } else {
+            let Some(id) = u16::try_from(self.links.len() + 1).ok().and_then(NonZeroU16::new)
+            else {
+                debug!("Hyperlink table full, dropping link to {}", link.uri);
+                return None;
+            };
+            self.links.push(Some(link.clone()));
+            HyperlinkId(id)
+        };
+        self.ids.insert(link.clone(), id);
+        Some(id)
+    }
+
+    /// Look up an interned hyperlink
+    pub fn get(&self, id: HyperlinkId) -> Option<&Hyperlink> {
+        self.links.get(id.index())?.as_ref()
+    }
+
+    /// Free the ids for which `keep` returns false, so that they can be
+    /// reused
+    pub fn retain(&mut self, mut keep: impl FnMut(HyperlinkId) -> bool) {
+        let (links, free) = (&mut self.links, &mut self.free);
+        self.ids.retain(|_, &mut id| {
+            if keep(id) {
+                return true;
+            }
+            links[id.index()] = None;
+            free.push(id);
+            false
+        });
+    }
+
+    /// Get the number of interned hyperlinks
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Check if the table is empty
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_deduplicates() {
+        let mut table = HyperlinkTable::new();
+        let a = table.intern(&Hyperlink::new(None, "https://example.com")).unwrap();
+        let b = table.intern(&Hyperlink::new(None, "https://example.com")).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(table.len(), 1);
+
+        // The same URI with a different id is a different link
+        let c = table.intern(&Hyperlink::new(Some("1".into()), "https://example.com")).unwrap();
+        assert_ne!(a, c);
+        assert_eq!(table.get(c).unwrap().id.as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut table = HyperlinkTable::new();
+        assert!(table.is_empty());
+        let id = table.intern(&Hyperlink::new(None, "file:///tmp")).unwrap();
+        assert_eq!(table.get(id).unwrap().uri, "file:///tmp");
+    }
+
+    #[test]
+    fn test_retain_frees_ids_for_reuse() {
+        let mut table = HyperlinkTable::new();
+        let a = table.intern(&Hyperlink::new(None, "https://a.example")).unwrap();
+        let b = table.intern(&Hyperlink::new(None, "https://b.example")).unwrap();
+        table.retain(|id| id == b);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.get(a), None);
+        assert_eq!(table.get(b).unwrap().uri, "https://b.example");
+
+        let c = table.intern(&Hyperlink::new(None, "https://c.example")).unwrap();
+        assert_eq!(c, a);
+        assert_eq!(table.get(c).unwrap().uri, "https://c.example");
+    }
+
+    #[test]
+    fn test_full_table() {
+        let mut table = HyperlinkTable::new();
+        for i in 0..u16::MAX {
+            assert!(table.intern(&Hyperlink::new(None, format!("https://{}", i))).is_some());
+        }
+        let link = Hyperlink::new(None, "https://new.example");
+        assert_eq!(table.intern(&link), None);
+
+        table.retain(|_| false);
+        assert!(table.is_empty());
+        assert!(table.intern(&link).is_some());
+    }
+}
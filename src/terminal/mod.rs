@@ -5,23 +5,46 @@
 //! - Cursor management
 //! - Color support
 //! - Character attributes
-//! - Scrolling and scroll regions
+//! - Scrolling and scroll regions, including left/right margins (DECLRMM)
+//! - Rectangular area operations (DECFRA, DECERA, DECSERA, DECCRA, DECCARA)
+//! - Background color erase (erased cells take the current background)
 //! - VTE parser integration
 //! - Terminal modes (application cursor keys, bracketed paste, etc.)
 //! - Alternate screen buffer
+//! - Character sets (G0-G3 with DEC Special Graphics and UK, SI/SO, SS2/SS3)
+//! - Tab stops
+//! - OSC 8 hyperlinks
+//! - Dynamic color palette (OSC 4/10/11/12/17)
+//! - Shell integration marks (OSC 133)
+//! - Working directory reporting (OSC 7)
+//! - Grapheme clusters (combining characters stored out of line)
+//! - Underline styles and colors (SGR 4:x, 58)
 
 pub mod cell;
+pub mod charset;
 pub mod color;
 pub mod cursor;
+pub mod cwd;
+pub mod extra;
 pub mod grid;
+pub mod hyperlink;
 pub mod modes;
+pub mod palette;
 pub mod parser;
 pub mod selection;
+pub mod semantic;
+pub mod tabstops;
 
-pub use cell::{Cell, CellFlags};
+pub use cell::{Cell, CellFlags, UnderlineStyle};
+pub use charset::{Charset, CharsetState};
 pub use color::{Color, NamedColor};
 pub use cursor::{Cursor, CursorStyle};
-pub use grid::Grid;
+pub use extra::{CellExtra, ExtraId, ExtraTable};
+pub use grid::{Grid, Rect};
+pub use hyperlink::{Hyperlink, HyperlinkId, HyperlinkTable};
 pub use modes::TerminalModes;
+pub use palette::{Palette, SpecialColor};
 pub use parser::Parser;
 pub use selection::{Point, Selection};
+pub use semantic::{CommandRecord, LineMark, SemanticMark};
+pub use tabstops::TabStops;
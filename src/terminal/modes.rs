@@ -12,6 +12,8 @@
 //! - Mouse reporting modes (X10, VT200, SGR, URXVT)
 //! - Focus reporting (1004)
 //! - Alternate screen mode (1049)
+//! - Left/right margin mode (DECLRMM)
+//! - Synchronized output (2026)
 
 use bitflags::bitflags;
 
@@ -71,6 +73,12 @@ bitflags! {
         
         /// Mouse URXVT extended mode
         const MOUSE_URXVT = 0b0001_0000_0000_0000_0000;
+        
+        /// Left/right margin mode (DECLRMM) - `CSI Pl ; Pr s` sets margins
+        const LEFT_RIGHT_MARGIN = 0b0010_0000_0000_0000_0000;
+        
+        /// Synchronized output (2026) - the application is drawing a frame
+        const SYNCHRONIZED_OUTPUT = 0b0100_0000_0000_0000_0000;
     }
 }
 
@@ -131,6 +139,16 @@ impl TerminalModes {
         self.contains(Self::INSERT_MODE)
     }
 
+    /// Check if left/right margin mode is active
+    pub fn is_left_right_margin(&self) -> bool {
+        self.contains(Self::LEFT_RIGHT_MARGIN)
+    }
+
+    /// Check if an application is in the middle of a synchronized update
+    pub fn is_synchronized_output(&self) -> bool {
+        self.contains(Self::SYNCHRONIZED_OUTPUT)
+    }
+
     /// Get the active mouse tracking mode
     pub fn mouse_mode(&self) -> MouseMode {
         if self.contains(Self::MOUSE_SGR) {
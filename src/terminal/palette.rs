@@ -0,0 +1,238 @@
+//! Dynamic color palette
+//!
+//! The palette holds the 256 indexed colors plus the special foreground,
+//! background, cursor and selection colors. It is seeded from the active
+//! [`Theme`] and can be changed at runtime by applications through the
+//! OSC 4/10/11/12/17/19 family, then reset back to the theme values.
+
+use super::color::{Color, NamedColor};
+use crate::config::theme::{Rgb, Theme};
+
+/// Levels of the 6x6x6 color cube (indexes 16-231)
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Special (non-indexed) color slots
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpecialColor {
+    /// Default foreground (OSC 10)
+    Foreground,
+    /// Default background (OSC 11)
+    Background,
+    /// Cursor color (OSC 12)
+    Cursor,
+    /// Selection background (OSC 17)
+    SelectionBackground,
+    /// Selection foreground (OSC 19)
+    SelectionForeground,
+}
+
+impl SpecialColor {
+    /// Look up the special color for an xterm dynamic color number
+    pub fn from_osc(number: u16) -> Option<Self> {
+        match number {
+            10 => Some(Self::Foreground),
+            11 => Some(Self::Background),
+            12 => Some(Self::Cursor),
+            17 => Some(Self::SelectionBackground),
+            19 => Some(Self::SelectionForeground),
+            _ => None,
+        }
+    }
+}
+
+/// Terminal color palette
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    /// Indexed colors (16 ANSI colors, 216 color cube, 24 grays)
+    indexed: [Rgb; 256],
+    /// Default foreground
+    foreground: Rgb,
+    /// Default background
+    background: Rgb,
+    /// Cursor color
+    cursor: Rgb,
+    /// Selection background
+    selection_background: Rgb,
+    /// Selection foreground
+    selection_foreground: Rgb,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::from_theme(&Theme::catppuccin_mocha())
+    }
+}
+
+impl Palette {
+    /// Create a palette from a theme
+    ///
+    /// The first 16 colors come from the theme; the rest use the standard
+    /// xterm 256-color layout.
+    pub fn from_theme(theme: &Theme) -> Self {
+        let mut indexed = [Rgb::new(0, 0, 0); 256];
+        indexed[..16].copy_from_slice(&theme.ansi);
+        for (i, color) in indexed.iter_mut().enumerate().skip(16) {
+            *color = if i < 232 {
+                let i = i - 16;
+                Rgb::new(CUBE_LEVELS[i / 36], CUBE_LEVELS[(i / 6) % 6], CUBE_LEVELS[i % 6])
+            } else {
+                let level = 8 + 10 * (i - 232) as u8;
+                Rgb::new(level, level, level)
+            };
+        }
+
+        Self {
+            indexed,
+            foreground: theme.foreground,
+            background: theme.background,
+            cursor: theme.cursor.unwrap_or(theme.foreground),
+            selection_background: theme.selection_background,
+            selection_foreground: theme.selection_foreground.unwrap_or(theme.foreground),
+        }
+    }
+
+    /// Get an indexed color
+    pub fn indexed(&self, index: u8) -> Rgb {
+        self.indexed[index as usize]
+    }
+
+    /// Set an indexed color
+    pub fn set_indexed(&mut self, index: u8, rgb: Rgb) {
+        self.indexed[index as usize] = rgb;
+    }
+
+    /// Get a special color
+    pub fn special(&self, slot: SpecialColor) -> Rgb {
+        match slot {
+            SpecialColor::Foreground => self.foreground,
+            SpecialColor::Background => self.background,
+            SpecialColor::Cursor => self.cursor,
+            SpecialColor::SelectionBackground => self.selection_background,
+            SpecialColor::SelectionForeground => self.selection_foreground,
+        }
+    }
+
+    /// Set a special color
+    pub fn set_special(&mut self, slot: SpecialColor, rgb: Rgb) {
+        match slot {
+            SpecialColor::Foreground => self.foreground = rgb,
+            SpecialColor::Background => self.background = rgb,
+            SpecialColor::Cursor => self.cursor = rgb,
+            SpecialColor::SelectionBackground => self.selection_background = rgb,
+            SpecialColor::SelectionForeground => self.selection_foreground = rgb,
+        }
+    }
+
+    /// Resolve a cell color to RGB
+    pub fn resolve(&self, color: Color) -> Rgb {
+        match color {
+            Color::Named(NamedColor::Foreground) => self.foreground,
+            Color::Named(NamedColor::Background) => self.background,
+            Color::Named(named) => self.indexed[named as usize],
+            Color::Indexed(index) => self.indexed[index as usize],
+            Color::Rgb(r, g, b) => Rgb::new(r, g, b),
+        }
+    }
+}
+
+/// Parse an X11 color specification
+///
+/// Supports `rgb:r/g/b` with 1-4 hex digits per component and the `#rgb`,
+/// `#rrggbb`, `#rrrgggbbb` and `#rrrrggggbbbb` forms.
+pub fn parse_color_spec(spec: &[u8]) -> Option<Rgb> {
+    let spec = std::str::from_utf8(spec).ok()?;
+
+    if let Some(rgb) = spec.strip_prefix("rgb:") {
+        let mut parts = rgb.split('/');
+        let r = scale_component(parts.next()?)?;
+        let g = scale_component(parts.next()?)?;
+        let b = scale_component(parts.next()?)?;
+        if parts.next().is_some() {
+            return None;
+        }
+        return Some(Rgb::new(r, g, b));
+    }
+
+    let hex = spec.strip_prefix('#')?;
+    if hex.is_empty() || hex.len() % 3 != 0 || hex.len() > 12 {
+        return None;
+    }
+    let width = hex.len() / 3;
+    let r = scale_component(&hex[..width])?;
+    let g = scale_component(&hex[width..2 * width])?;
+    let b = scale_component(&hex[2 * width..])?;
+    Some(Rgb::new(r, g, b))
+}
+
+/// Format a color as an xterm `rgb:rrrr/gggg/bbbb` reply
+pub fn format_color_spec(rgb: Rgb) -> String {
+    format!("rgb:{0:02x}{0:02x}/{1:02x}{1:02x}/{2:02x}{2:02x}", rgb.r, rgb.g, rgb.b)
+}
+
+/// Scale a 1-4 digit hex component to 8 bits
+fn scale_component(hex: &str) -> Option<u8> {
+    if hex.is_empty() || hex.len() > 4 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    let max = (1u32 << (4 * hex.len())) - 1;
+    Some(((value * 255 + max / 2) / max) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palette_from_theme() {
+        let theme = Theme::dracula();
+        let palette = Palette::from_theme(&theme);
+        assert_eq!(palette.indexed(1), theme.ansi[1]);
+        assert_eq!(palette.special(SpecialColor::Background), theme.background);
+        // Dracula has no selection foreground, so it follows the foreground
+        assert_eq!(palette.special(SpecialColor::SelectionForeground), theme.foreground);
+    }
+
+    #[test]
+    fn test_palette_256_color_layout() {
+        let palette = Palette::default();
+        assert_eq!(palette.indexed(16), Rgb::new(0, 0, 0));
+        assert_eq!(palette.indexed(196), Rgb::new(255, 0, 0));
+        assert_eq!(palette.indexed(231), Rgb::new(255, 255, 255));
+        assert_eq!(palette.indexed(232), Rgb::new(8, 8, 8));
+        assert_eq!(palette.indexed(255), Rgb::new(238, 238, 238));
+    }
+
+    #[test]
+    fn test_palette_resolve() {
+        let mut palette = Palette::default();
+        palette.set_indexed(2, Rgb::new(1, 2, 3));
+        assert_eq!(palette.resolve(Color::Named(NamedColor::Green)), Rgb::new(1, 2, 3));
+        assert_eq!(palette.resolve(Color::Indexed(2)), Rgb::new(1, 2, 3));
+        assert_eq!(palette.resolve(Color::Rgb(9, 8, 7)), Rgb::new(9, 8, 7));
+
+        palette.set_special(SpecialColor::Foreground, Rgb::new(4, 5, 6));
+        assert_eq!(palette.resolve(Color::default()), Rgb::new(4, 5, 6));
+    }
+
+    #[test]
+    fn test_parse_color_spec() {
+        assert_eq!(parse_color_spec(b"rgb:ff/80/00"), Some(Rgb::new(255, 128, 0)));
+        assert_eq!(parse_color_spec(b"rgb:ffff/8080/0000"), Some(Rgb::new(255, 128, 0)));
+        assert_eq!(parse_color_spec(b"rgb:f/8/0"), Some(Rgb::new(255, 136, 0)));
+        assert_eq!(parse_color_spec(b"#ff8000"), Some(Rgb::new(255, 128, 0)));
+        assert_eq!(parse_color_spec(b"#f80"), Some(Rgb::new(255, 136, 0)));
+        assert_eq!(parse_color_spec(b"#ffff80800000"), Some(Rgb::new(255, 128, 0)));
+
+        assert_eq!(parse_color_spec(b"rgb:ff/80"), None);
+        assert_eq!(parse_color_spec(b"rgb:ff/80/00/00"), None);
+        assert_eq!(parse_color_spec(b"rgb:fffff/0/0"), None);
+        assert_eq!(parse_color_spec(b"#ff80"), None);
+        assert_eq!(parse_color_spec(b"red"), None);
+    }
+
+    #[test]
+    fn test_format_color_spec() {
+        assert_eq!(format_color_spec(Rgb::new(255, 128, 0)), "rgb:ffff/8080/0000");
+    }
+}
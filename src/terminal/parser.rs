@@ -3,9 +3,27 @@
 //! This module integrates the `vte` crate to parse ANSI escape sequences
 //! and dispatch them to the terminal emulator.
 
-use super::{cell::CellFlags, color::Color, cursor::Cursor, grid::Grid, modes::TerminalModes};
+use super::{
+    cell::{Cell, CellFlags, UnderlineStyle},
+    charset::{Charset, CharsetState},
+    color::{Color, NamedColor},
+    cursor::{Cursor, CursorStyle},
+    cwd::{self, ReportedDirectory},
+    extra::CellExtra,
+    grid::{Grid, Rect},
+    hyperlink::Hyperlink,
+    modes::TerminalModes,
+    palette::{self, Palette, SpecialColor},
+    semantic::{CommandRecord, SemanticMark},
+    tabstops::TabStops,
+};
+use crate::clipboard::{self, ClipboardReadPolicy, ClipboardRequest};
+use crate::config::theme::Theme;
+use crate::notification::{KittyNotifications, Notification};
+use std::path::{Path, PathBuf};
 use tracing::{debug, trace};
-use vte::{Params, Perform};
+use unicode_width::UnicodeWidthChar;
+use vte::{Params, ParamsIter, Perform};
 
 /// Terminal parser state
 pub struct Parser {
@@ -25,18 +43,132 @@ struct TerminalState {
     active_grid: GridType,
     /// Cursor position
     cursor: Cursor,
-    /// Saved cursor position for primary screen
-    saved_cursor_primary: Cursor,
-    /// Saved cursor position for alternate screen
-    saved_cursor_alternate: Cursor,
+    /// A character was printed in the last column and the next one wraps
+    wrap_pending: bool,
+    /// Cursor state saved by DECSC on the primary screen
+    saved_cursor_primary: SavedCursor,
+    /// Cursor state saved by DECSC on the alternate screen
+    saved_cursor_alternate: SavedCursor,
     /// Current foreground color
     current_fg: Color,
     /// Current background color
     current_bg: Color,
     /// Current cell flags (bold, italic, etc.)
     current_flags: CellFlags,
+    /// Current underline color (SGR 58)
+    current_underline_color: Option<Color>,
+    /// Printed characters are protected from selective erase (DECSCA)
+    current_protected: bool,
+    /// Current OSC 8 hyperlink
+    current_hyperlink: Option<Hyperlink>,
     /// Terminal modes
     modes: TerminalModes,
+    /// A synchronized update finished since the UI last took a frame
+    frame_completed: bool,
+    /// Top margin of the scrolling region (0-indexed, inclusive)
+    scroll_top: usize,
+    /// Bottom margin of the scrolling region (0-indexed, inclusive)
+    scroll_bottom: usize,
+    /// Left margin (0-indexed, inclusive), set by DECSLRM
+    scroll_left: usize,
+    /// Right margin (0-indexed, inclusive), set by DECSLRM
+    scroll_right: usize,
+    /// DECCARA changes a rectangle rather than a stream of cells (DECSACE)
+    rectangular_attribute_extent: bool,
+    /// Bytes queued for the host (replies to device queries)
+    responses: Vec<u8>,
+    /// Horizontal tab stops
+    tab_stops: TabStops,
+    /// Designated G0-G3 character sets
+    charsets: CharsetState,
+    /// Last printed character, repeated by REP
+    last_char: Option<char>,
+    /// Window title (OSC 0/2)
+    title: Option<String>,
+    /// Icon name (OSC 0/1)
+    icon_name: Option<String>,
+    /// Saved titles for XTWINOPS 22/23 as `(title, icon_name)`, with None
+    /// for the ones that were not saved
+    title_stack: Vec<(Option<Option<String>>, Option<Option<String>>)>,
+    /// Clipboard requests (OSC 52) waiting to be handled by the UI
+    clipboard_requests: Vec<ClipboardRequest>,
+    /// Policy for OSC 52 clipboard reads
+    clipboard_read_policy: ClipboardReadPolicy,
+    /// Current color palette, changed by OSC 4/10/11/12/17/19
+    palette: Palette,
+    /// Palette seeded from the theme, restored by the OSC 104/110-119 resets
+    default_palette: Palette,
+    /// Working directory reported by the shell (OSC 7)
+    working_directory: Option<PathBuf>,
+    /// Notifications (OSC 9/99/777) waiting to be shown by the UI
+    notifications: Vec<Notification>,
+    /// Partially received OSC 99 notification
+    kitty_notifications: KittyNotifications,
+    /// Setting requested by a DECRQSS (`DCS $ q`) being received
+    decrqss: Option<Vec<u8>>,
+}
+
+/// Reply to DA1: VT220 with ANSI color
+const PRIMARY_DEVICE_ATTRIBUTES: &[u8] = b"\x1b[?62;22c";
+
+/// Reply to DA2: VT220, firmware version 10, no ROM cartridge
+const SECONDARY_DEVICE_ATTRIBUTES: &[u8] = b"\x1b[>1;10;0c";
+
+/// Maximum length of an OSC 8 hyperlink URI
+const MAX_HYPERLINK_URI_LEN: usize = 4096;
+
+/// Maximum size of an OSC 52 payload (base64 encoded)
+const MAX_CLIPBOARD_PAYLOAD_LEN: usize = 1024 * 1024;
+
+/// Maximum depth of the title stack (matches xterm)
+const MAX_TITLE_STACK: usize = 10;
+
+/// Maximum length of a DECRQSS request; valid ones are at most two bytes
+const MAX_DECRQSS_LEN: usize = 8;
+
+/// Cursor state saved by DECSC and restored by DECRC
+///
+/// Besides the position this holds the SGR pen, DECSCA protection, origin
+/// mode and character sets. Each screen has its own saved state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SavedCursor {
+    /// Column position (0-indexed)
+    col: usize,
+    /// Row position (0-indexed)
+    row: usize,
+    /// A wrap was pending (the cursor was past the last column)
+    wrap_pending: bool,
+    /// Foreground color
+    fg: Color,
+    /// Background color
+    bg: Color,
+    /// Cell flags (bold, italic, etc.)
+    flags: CellFlags,
+    /// Underline color
+    underline_color: Option<Color>,
+    /// Protection from selective erase (DECSCA)
+    protected: bool,
+    /// Origin mode (DECOM)
+    origin_mode: bool,
+    /// Designated G0-G3 character sets
+    charsets: CharsetState,
+}
+
+impl Default for SavedCursor {
+    fn default() -> Self {
+        Self {
+            col: 0,
+            row: 0,
+            wrap_pending: false,
+            fg: Color::default(),
+            bg: Color::Named(super::color::NamedColor::Background),
+            flags: CellFlags::empty(),
+            underline_color: None,
+            protected: false,
+            origin_mode: false,
+            charsets: CharsetState::new(),
+        }
+    }
 }
 
 /// Enum to track which grid is active
@@ -49,21 +181,7 @@ enum GridType {
 impl Parser {
     /// Create a new parser with the given grid dimensions
     pub fn new(cols: usize, rows: usize) -> Self {
-        Self {
-            parser: vte::Parser::new(),
-            terminal: TerminalState {
-                primary_grid: Grid::new(cols, rows, 10_000),
-                alternate_grid: Grid::new(cols, rows, 0), // No scrollback for alt screen
-                active_grid: GridType::Primary,
-                cursor: Cursor::default(),
-                saved_cursor_primary: Cursor::default(),
-                saved_cursor_alternate: Cursor::default(),
-                current_fg: Color::default(),
-                current_bg: Color::Named(super::color::NamedColor::Background),
-                current_flags: CellFlags::empty(),
-                modes: TerminalModes::new(),
-            },
-        }
+        Self { parser: vte::Parser::new(), terminal: TerminalState::new(cols, rows) }
     }
 
     /// Advance the parser with a byte of data
@@ -112,9 +230,487 @@ impl Parser {
     pub fn is_alt_screen(&self) -> bool {
         self.terminal.active_grid == GridType::Alternate
     }
+
+    /// Check if the screen holds a complete frame that can be presented
+    ///
+    /// This is false while an application batches an update with
+    /// synchronized output (mode 2026); the UI should keep showing the
+    /// previous frame until the update is finished.
+    pub fn is_frame_ready(&self) -> bool {
+        !self.terminal.modes.is_synchronized_output()
+    }
+
+    /// Check if there is a frame to present, consuming a finished update
+    ///
+    /// Unlike [`Self::is_frame_ready`] this is also true if a synchronized
+    /// update finished and the next one already started since the last
+    /// call, e.g. when both arrived in the same read from the PTY.
+    pub fn take_frame_ready(&mut self) -> bool {
+        std::mem::take(&mut self.terminal.frame_completed) || self.is_frame_ready()
+    }
+
+    /// End an unfinished synchronized update
+    ///
+    /// Used when an update takes too long, so that an application that never
+    /// resets mode 2026 does not keep the screen from updating.
+    pub fn end_synchronized_update(&mut self) {
+        self.terminal.modes.remove(TerminalModes::SYNCHRONIZED_OUTPUT);
+    }
+
+    /// Take the bytes queued for the host, leaving the queue empty
+    ///
+    /// Replies to device queries (DA, DSR, CPR, DECRQM) are queued while
+    /// parsing and must be written back to the PTY by the caller.
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.terminal.responses)
+    }
+
+    /// Get the window title set by the application, if any
+    pub fn title(&self) -> Option<&str> {
+        self.terminal.title.as_deref()
+    }
+
+    /// Get the icon name set by the application, if any
+    pub fn icon_name(&self) -> Option<&str> {
+        self.terminal.icon_name.as_deref()
+    }
+
+    /// Set the policy for applications reading the clipboard via OSC 52
+    pub fn set_clipboard_read_policy(&mut self, policy: ClipboardReadPolicy) {
+        self.terminal.clipboard_read_policy = policy;
+    }
+
+    /// Take the pending OSC 52 clipboard requests
+    ///
+    /// Store requests should be copied to the system clipboard; load requests
+    /// are answered with [`Parser::respond_clipboard`].
+    pub fn take_clipboard_requests(&mut self) -> Vec<ClipboardRequest> {
+        std::mem::take(&mut self.terminal.clipboard_requests)
+    }
+
+    /// Answer an OSC 52 clipboard read with the clipboard contents
+    ///
+    /// The reply is queued with the other responses for the host. An empty
+    /// `text` answers a read that was denied.
+    pub fn respond_clipboard(&mut self, selection: &str, text: &str) {
+        let reply = format!("\x1b]52;{};{}\x1b\\", selection, clipboard::encode_osc52(text));
+        self.terminal.respond(reply.as_bytes());
+    }
+
+    /// Get the current color palette
+    pub fn palette(&self) -> &Palette {
+        &self.terminal.palette
+    }
+
+    /// Seed the palette from a theme
+    ///
+    /// Colors changed by the application are discarded; the theme also
+    /// becomes the target of the OSC color resets.
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.terminal.default_palette = Palette::from_theme(theme);
+        self.terminal.palette = self.terminal.default_palette.clone();
+    }
+
+    /// Get the working directory last reported by the shell via OSC 7
+    ///
+    /// Returns None once the shell reports a directory on another host.
+    pub fn working_directory(&self) -> Option<&Path> {
+        self.terminal.working_directory.as_deref()
+    }
+
+    /// Take the notifications requested by applications (OSC 9/99/777)
+    pub fn take_notifications(&mut self) -> Vec<Notification> {
+        std::mem::take(&mut self.terminal.notifications)
+    }
+
+    /// Get the commands recorded by OSC 133 shell integration, oldest first
+    ///
+    /// See [`Grid::commands`] for how points are numbered.
+    pub fn commands(&self) -> Vec<CommandRecord> {
+        self.grid().commands()
+    }
+
+    /// Get the output of a command recorded by shell integration
+    pub fn command_output(&self, command: &CommandRecord) -> String {
+        self.grid().command_output(command)
+    }
+
+    /// Check if there are bytes queued for the host
+    pub fn has_responses(&self) -> bool {
+        !self.terminal.responses.is_empty()
+    }
+
+    /// Get the scrolling region as `(top, bottom)` rows (0-indexed, inclusive)
+    pub fn scroll_region(&self) -> (usize, usize) {
+        (self.terminal.scroll_top, self.terminal.scroll_bottom)
+    }
+
+    /// Get the left and right margins as `(left, right)` columns (0-indexed,
+    /// inclusive)
+    pub fn horizontal_margins(&self) -> (usize, usize) {
+        (self.terminal.scroll_left, self.terminal.scroll_right)
+    }
+
+    /// Resize both screens to the given dimensions
+    ///
+    /// The scrolling region and margins are reset to the full screen, tab
+    /// stops are extended to the new width and the cursor is clamped to the
+    /// new bounds.
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        self.terminal.resize(cols, rows);
+    }
 }
 
 impl TerminalState {
+    /// Create terminal state for a screen of the given dimensions
+    fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            primary_grid: Grid::new(cols, rows, 10_000),
+            alternate_grid: Grid::new(cols, rows, 0), // No scrollback for alt screen
+            active_grid: GridType::Primary,
+            cursor: Cursor::default(),
+            wrap_pending: false,
+            saved_cursor_primary: SavedCursor::default(),
+            saved_cursor_alternate: SavedCursor::default(),
+            current_fg: Color::default(),
+            current_bg: Color::Named(super::color::NamedColor::Background),
+            current_flags: CellFlags::empty(),
+            current_underline_color: None,
+            current_protected: false,
+            current_hyperlink: None,
+            modes: TerminalModes::new(),
+            scroll_top: 0,
+            scroll_bottom: rows.saturating_sub(1),
+            scroll_left: 0,
+            scroll_right: cols.saturating_sub(1),
+            rectangular_attribute_extent: false,
+            frame_completed: false,
+            responses: Vec::new(),
+            tab_stops: TabStops::new(cols),
+            charsets: CharsetState::new(),
+            last_char: None,
+            title: None,
+            icon_name: None,
+            title_stack: Vec::new(),
+            clipboard_requests: Vec::new(),
+            clipboard_read_policy: ClipboardReadPolicy::default(),
+            palette: Palette::default(),
+            default_palette: Palette::default(),
+            working_directory: None,
+            notifications: Vec::new(),
+            kitty_notifications: KittyNotifications::new(),
+            decrqss: None,
+        }
+    }
+
+    /// Reset the terminal to its initial state (RIS)
+    ///
+    /// Both screens, the scrollback, modes, tab stops and character sets are
+    /// reset; only the dimensions, user settings and replies not yet sent to
+    /// the host are kept.
+    fn reset(&mut self) {
+        let cols = self.grid().cols();
+        let rows = self.grid().rows();
+        let responses = std::mem::take(&mut self.responses);
+        let clipboard_requests = std::mem::take(&mut self.clipboard_requests);
+        let clipboard_read_policy = self.clipboard_read_policy;
+        let default_palette = std::mem::take(&mut self.default_palette);
+        let working_directory = self.working_directory.take();
+        let notifications = std::mem::take(&mut self.notifications);
+        *self = Self::new(cols, rows);
+        // Ends an unfinished synchronized update
+        self.frame_completed = true;
+        self.working_directory = working_directory;
+        self.notifications = notifications;
+        self.palette = default_palette.clone();
+        self.default_palette = default_palette;
+        self.responses = responses;
+        self.clipboard_requests = clipboard_requests;
+        self.clipboard_read_policy = clipboard_read_policy;
+        debug!("Terminal reset");
+    }
+
+    /// Save the title and/or icon name on the title stack (XTWINOPS 22)
+    ///
+    /// `which` selects what to save: 0 = both, 1 = icon name, 2 = title.
+    fn push_title(&mut self, which: u16) {
+        let title = (which != 1).then(|| self.title.clone());
+        let icon_name = (which != 2).then(|| self.icon_name.clone());
+        if self.title_stack.len() >= MAX_TITLE_STACK {
+            self.title_stack.remove(0);
+        }
+        self.title_stack.push((title, icon_name));
+    }
+
+    /// Restore the title and/or icon name from the title stack (XTWINOPS 23)
+    ///
+    /// `which` selects what to restore: 0 = both, 1 = icon name, 2 = title.
+    /// Only what was saved by the matching push is restored.
+    fn pop_title(&mut self, which: u16) {
+        if let Some((title, icon_name)) = self.title_stack.pop() {
+            if let Some(title) = title.filter(|_| which != 1) {
+                self.title = title;
+            }
+            if let Some(icon_name) = icon_name.filter(|_| which != 2) {
+                self.icon_name = icon_name;
+            }
+        }
+    }
+
+    /// Create a cell drawn with the current pen (SGR attributes, hyperlink
+    /// and DECSCA protection)
+    fn pen(&mut self, c: char) -> Cell {
+        let mut cell = Cell::with_colors(c, self.current_fg, self.current_bg);
+        cell.flags = self.current_flags;
+        // The link is taken out while the grid is borrowed to intern it
+        let hyperlink = self.current_hyperlink.take().and_then(|link| {
+            let id = self.grid().intern_hyperlink(&link);
+            self.current_hyperlink = Some(link);
+            id
+        });
+        let extra = CellExtra {
+            hyperlink,
+            underline_color: self.current_underline_color,
+            protected: self.current_protected,
+            ..CellExtra::default()
+        };
+        // Most cells have no extra data and need no table lookup
+        if !extra.is_empty() {
+            cell.extra = self.grid().intern_extra(&extra);
+        }
+        cell
+    }
+
+    /// Attach a zero-width character to the previously printed cell
+    ///
+    /// Characters at the start of a line have nothing to attach to and are
+    /// dropped.
+    fn push_zerowidth(&mut self, c: char) {
+        let row = self.cursor.row;
+        // With a pending wrap the previous character is still under the cursor
+        let col = if self.wrap_pending {
+            self.cursor.col
+        } else if let Some(col) = self.cursor.col.checked_sub(1) {
+            col
+        } else {
+            debug!("Dropping zero-width character {:?} at the start of a line", c);
+            return;
+        };
+
+        // The cluster of a wide character lives in its left half
+        let grid = self.grid();
+        let has_flag = |col, flag| grid.get(col, row).is_some_and(|cell| cell.flags.contains(flag));
+        let col = if col > 0
+            && has_flag(col, CellFlags::WIDE_CHAR_SPACER)
+            && has_flag(col - 1, CellFlags::WIDE_CHAR)
+        {
+            col - 1
+        } else {
+            col
+        };
+        grid.push_zerowidth(col, row, c);
+    }
+
+    /// Start or end an OSC 8 hyperlink
+    ///
+    /// `link_params` holds colon-separated `key=value` pairs (only `id` is
+    /// used); an empty URI ends the current link.
+    fn set_hyperlink(&mut self, link_params: &[u8], uri: &[&[u8]]) {
+        let uri = uri.join(&b';');
+        if uri.is_empty() {
+            self.current_hyperlink = None;
+            return;
+        }
+        if uri.len() > MAX_HYPERLINK_URI_LEN {
+            debug!("Ignoring hyperlink with {} byte URI", uri.len());
+            self.current_hyperlink = None;
+            return;
+        }
+
+        let id = link_params
+            .split(|&b| b == b':')
+            .find_map(|pair| pair.strip_prefix(b"id="))
+            .filter(|id| !id.is_empty())
+            .map(|id| String::from_utf8_lossy(id).into_owned());
+        self.current_hyperlink = Some(Hyperlink::new(id, String::from_utf8_lossy(&uri)));
+    }
+
+    /// Handle an OSC 52 clipboard store (base64 data) or load (`?`) request
+    ///
+    /// A denied load is answered with empty data, ended by `terminator`.
+    fn handle_clipboard(&mut self, selection: &[u8], data: &[u8], terminator: &str) {
+        let selection = match String::from_utf8_lossy(selection).into_owned() {
+            selection if selection.is_empty() => String::from("s0"),
+            selection => selection,
+        };
+
+        if data == b"?" {
+            let confirm = match self.clipboard_read_policy {
+                ClipboardReadPolicy::Deny => {
+                    debug!("Denied OSC 52 clipboard read");
+                    self.respond(format!("\x1b]52;{};{}", selection, terminator).as_bytes());
+                    return;
+                }
+                ClipboardReadPolicy::Ask => true,
+                ClipboardReadPolicy::Allow => false,
+            };
+            self.clipboard_requests.push(ClipboardRequest::Load { selection, confirm });
+            return;
+        }
+
+        if data.len() > MAX_CLIPBOARD_PAYLOAD_LEN {
+            debug!("Ignoring {} byte OSC 52 payload", data.len());
+            return;
+        }
+        match clipboard::decode_osc52(data) {
+            Some(text) => self.clipboard_requests.push(ClipboardRequest::Store { selection, text }),
+            None => debug!("Ignoring OSC 52 payload that is not base64 encoded text"),
+        }
+    }
+
+    /// Set or query indexed colors (OSC 4)
+    ///
+    /// `params` holds `index;spec` pairs; a `?` spec queries the color.
+    fn set_indexed_colors(&mut self, params: &[&[u8]], terminator: &str) {
+        for pair in params.chunks(2) {
+            let [index, spec] = pair else {
+                break;
+            };
+            let Some(index) = parse_number(index).and_then(|i| u8::try_from(i).ok()) else {
+                debug!("Ignoring OSC 4 with invalid color index {:?}", index);
+                continue;
+            };
+            if *spec == b"?" {
+                let rgb = self.palette.indexed(index);
+                let reply =
+                    format!("\x1b]4;{};{}{}", index, palette::format_color_spec(rgb), terminator);
+                self.respond(reply.as_bytes());
+            } else if let Some(rgb) = palette::parse_color_spec(spec) {
+                self.palette.set_indexed(index, rgb);
+            } else {
+                debug!("Ignoring OSC 4 with invalid color {:?}", String::from_utf8_lossy(spec));
+            }
+        }
+    }
+
+    /// Reset indexed colors to the theme (OSC 104)
+    ///
+    /// Without parameters the whole palette is reset.
+    fn reset_indexed_colors(&mut self, params: &[&[u8]]) {
+        if params.iter().all(|p| p.is_empty()) {
+            for index in 0..=u8::MAX {
+                self.palette.set_indexed(index, self.default_palette.indexed(index));
+            }
+            return;
+        }
+        for index in params.iter().filter_map(|p| parse_number(p)) {
+            if let Ok(index) = u8::try_from(index) {
+                self.palette.set_indexed(index, self.default_palette.indexed(index));
+            }
+        }
+    }
+
+    /// Set or query special colors (OSC 10/11/12/17/19)
+    ///
+    /// Like xterm, each additional spec applies to the next color number, so
+    /// `OSC 10 ; fg ; bg` sets both the foreground and the background.
+    fn set_special_colors(&mut self, number: u16, specs: &[&[u8]], terminator: &str) {
+        for (number, spec) in (number..).zip(specs) {
+            let Some(slot) = SpecialColor::from_osc(number) else {
+                continue;
+            };
+            if *spec == b"?" {
+                let rgb = self.palette.special(slot);
+                let reply =
+                    format!("\x1b]{};{}{}", number, palette::format_color_spec(rgb), terminator);
+                self.respond(reply.as_bytes());
+            } else if let Some(rgb) = palette::parse_color_spec(spec) {
+                self.palette.set_special(slot, rgb);
+            } else {
+                debug!(
+                    "Ignoring OSC {} with invalid color {:?}",
+                    number,
+                    String::from_utf8_lossy(spec)
+                );
+            }
+        }
+    }
+
+    /// Reset a special color to the theme (OSC 110/111/112/117/119)
+    fn reset_special_color(&mut self, slot: SpecialColor) {
+        self.palette.set_special(slot, self.default_palette.special(slot));
+    }
+
+    /// Reply to a DECRQSS request with the current value of a setting
+    ///
+    /// Supports SGR (`m`), DECSTBM (`r`), DECSLRM (`s`), DECSCUSR (` q`)
+    /// and DECSCA (`"q`); anything else is reported as invalid.
+    fn report_setting(&mut self, request: &[u8]) {
+        let setting = match request {
+            b"m" => Some(format!("{}m", self.sgr_report())),
+            b"r" => Some(format!("{};{}r", self.scroll_top + 1, self.scroll_bottom + 1)),
+            b"s" => Some(format!("{};{}s", self.scroll_left + 1, self.scroll_right + 1)),
+            b" q" => Some(format!("{} q", self.cursor.style.decscusr(self.cursor.blinking))),
+            b"\"q" => Some(format!("{}\"q", u8::from(self.current_protected))),
+            _ => None,
+        };
+        match setting {
+            Some(setting) => self.respond(format!("\x1bP1$r{}\x1b\\", setting).as_bytes()),
+            None => {
+                debug!("Unsupported DECRQSS: {:?}", String::from_utf8_lossy(request));
+                self.respond(b"\x1bP0$r\x1b\\");
+            }
+        }
+    }
+
+    /// Describe the current graphic rendition as SGR parameters
+    fn sgr_report(&self) -> String {
+        let mut params = vec![String::from("0")];
+        let flags = self.current_flags;
+        for (flag, param) in [
+            (CellFlags::BOLD, "1"),
+            (CellFlags::DIM, "2"),
+            (CellFlags::ITALIC, "3"),
+            (CellFlags::BLINK, "5"),
+            (CellFlags::INVERSE, "7"),
+            (CellFlags::HIDDEN, "8"),
+            (CellFlags::STRIKETHROUGH, "9"),
+            (CellFlags::OVERLINE, "53"),
+        ] {
+            if flags.contains(flag) {
+                params.push(param.into());
+            }
+        }
+        match flags.underline_style() {
+            Some(UnderlineStyle::Single) => params.push("4".into()),
+            Some(style) => params.push(format!("4:{}", style.sgr())),
+            None => {}
+        }
+        params.extend(sgr_color_param(self.current_fg, 38));
+        params.extend(sgr_color_param(self.current_bg, 48));
+        params.extend(self.current_underline_color.and_then(|color| sgr_color_param(color, 58)));
+        params.join(";")
+    }
+
+    /// Queue bytes to be sent back to the host
+    fn respond(&mut self, bytes: &[u8]) {
+        trace!("Response: {:?}", bytes);
+        self.responses.extend_from_slice(bytes);
+    }
+
+    /// Get the 1-indexed cursor position for reports as `(col, row)`
+    ///
+    /// In origin mode the position is relative to the margins.
+    fn report_position(&mut self) -> (usize, usize) {
+        if self.modes.is_origin_mode() {
+            let col = self.cursor.col.saturating_sub(self.scroll_left) + 1;
+            let row = self.cursor.row.saturating_sub(self.scroll_top) + 1;
+            (col, row)
+        } else {
+            (self.cursor.col + 1, self.cursor.row + 1)
+        }
+    }
+
     /// Get the active grid
     fn grid(&mut self) -> &mut Grid {
         match self.active_grid {
@@ -124,14 +720,12 @@ impl TerminalState {
     }
 
     /// Switch to alternate screen
+    ///
+    /// The cursor is shared between the screens and stays where it is.
     fn use_alternate_screen(&mut self) {
         if self.active_grid == GridType::Primary {
-            // Save current cursor position
-            self.saved_cursor_primary = self.cursor.clone();
             // Switch to alternate screen
             self.active_grid = GridType::Alternate;
-            // Restore alternate screen cursor
-            self.cursor = self.saved_cursor_alternate.clone();
             // Set mode flag
             self.modes.insert(TerminalModes::ALT_SCREEN);
             debug!("Switched to alternate screen");
@@ -141,113 +735,660 @@ impl TerminalState {
     /// Switch to primary screen
     fn use_primary_screen(&mut self) {
         if self.active_grid == GridType::Alternate {
-            // Save alternate cursor position
-            self.saved_cursor_alternate = self.cursor.clone();
             // Switch to primary screen
             self.active_grid = GridType::Primary;
-            // Restore primary screen cursor
-            self.cursor = self.saved_cursor_primary.clone();
             // Clear mode flag
             self.modes.remove(TerminalModes::ALT_SCREEN);
             debug!("Switched to primary screen");
         }
     }
+
+    /// Resize both grids and tab stops, reset the scrolling region and
+    /// margins and clamp the cursor
+    fn resize(&mut self, cols: usize, rows: usize) {
+        self.primary_grid.resize(cols, rows);
+        self.alternate_grid.resize(cols, rows);
+        self.tab_stops.resize(cols);
+        self.scroll_top = 0;
+        self.scroll_bottom = rows.saturating_sub(1);
+        self.scroll_left = 0;
+        self.scroll_right = cols.saturating_sub(1);
+        self.wrap_pending = false;
+        self.cursor.col = self.cursor.col.min(cols.saturating_sub(1));
+        self.cursor.row = self.cursor.row.min(rows.saturating_sub(1));
+    }
+
+    /// Save the cursor state for the active screen (DECSC)
+    fn save_cursor(&mut self) {
+        let saved = SavedCursor {
+            col: self.cursor.col,
+            row: self.cursor.row,
+            wrap_pending: self.wrap_pending,
+            fg: self.current_fg,
+            bg: self.current_bg,
+            flags: self.current_flags,
+            underline_color: self.current_underline_color,
+            protected: self.current_protected,
+            origin_mode: self.modes.contains(TerminalModes::ORIGIN_MODE),
+            charsets: self.charsets,
+        };
+        match self.active_grid {
+            GridType::Primary => self.saved_cursor_primary = saved,
+            GridType::Alternate => self.saved_cursor_alternate = saved,
+        }
+        debug!("Cursor saved");
+    }
+
+    /// Restore the cursor state saved for the active screen (DECRC)
+    ///
+    /// Without a prior save the cursor is homed and the pen is reset.
+    fn restore_cursor(&mut self) {
+        let saved = match self.active_grid {
+            GridType::Primary => self.saved_cursor_primary.clone(),
+            GridType::Alternate => self.saved_cursor_alternate.clone(),
+        };
+
+        // The screen may have shrunk since the cursor was saved
+        let max_col = self.grid().cols() - 1;
+        let max_row = self.grid().rows() - 1;
+        self.cursor.col = saved.col.min(max_col);
+        self.cursor.row = saved.row.min(max_row);
+        self.wrap_pending = saved.wrap_pending;
+
+        self.current_fg = saved.fg;
+        self.current_bg = saved.bg;
+        self.current_flags = saved.flags;
+        self.current_underline_color = saved.underline_color;
+        self.current_protected = saved.protected;
+        self.sync_background();
+        self.modes.set(TerminalModes::ORIGIN_MODE, saved.origin_mode);
+        self.charsets = saved.charsets;
+        debug!("Cursor restored");
+    }
+
+    /// Fill the screen with 'E' for alignment checks (DECALN)
+    ///
+    /// Also resets the scrolling region and margins and homes the cursor.
+    fn screen_alignment_test(&mut self) {
+        let cols = self.grid().cols();
+        let rows = self.grid().rows();
+        for row in 0..rows {
+            for col in 0..cols {
+                self.grid().set(col, row, Cell::new('E'));
+            }
+        }
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.scroll_left = 0;
+        self.scroll_right = cols - 1;
+        self.cursor.goto(0, 0);
+        self.wrap_pending = false;
+    }
+
+    /// Insert n lines at the cursor within the scrolling region (IL)
+    ///
+    /// Only the columns between the left and right margins move.
+    fn insert_lines(&mut self, n: usize) {
+        if !self.cursor_in_margins() {
+            return;
+        }
+        let row = self.cursor.row;
+        if self.has_horizontal_margins() {
+            let rect = Rect { top: row, ..self.margin_rect() };
+            self.grid().scroll_rect_down(rect, n);
+        } else {
+            let bottom = self.scroll_bottom;
+            self.grid().insert_lines(row, bottom, n);
+        }
+        self.cursor.col = self.scroll_left;
+        self.wrap_pending = false;
+    }
+
+    /// Delete n lines at the cursor within the scrolling region (DL)
+    ///
+    /// Only the columns between the left and right margins move.
+    fn delete_lines(&mut self, n: usize) {
+        if !self.cursor_in_margins() {
+            return;
+        }
+        let row = self.cursor.row;
+        if self.has_horizontal_margins() {
+            let rect = Rect { top: row, ..self.margin_rect() };
+            self.grid().scroll_rect_up(rect, n);
+        } else {
+            let bottom = self.scroll_bottom;
+            self.grid().delete_lines(row, bottom, n);
+        }
+        self.cursor.col = self.scroll_left;
+        self.wrap_pending = false;
+    }
+
+    /// Check if the scrolling region covers the whole screen
+    fn is_full_scroll_region(&mut self) -> bool {
+        self.scroll_top == 0 && self.scroll_bottom + 1 >= self.grid().rows()
+    }
+
+    /// Check if left or right margins narrow the scrolling region
+    fn has_horizontal_margins(&mut self) -> bool {
+        self.scroll_left > 0 || self.scroll_right + 1 < self.grid().cols()
+    }
+
+    /// Get the area enclosed by all four margins
+    fn margin_rect(&self) -> Rect {
+        Rect::new(self.scroll_top, self.scroll_left, self.scroll_bottom, self.scroll_right)
+    }
+
+    /// Check if the cursor is inside all four margins
+    fn cursor_in_margins(&self) -> bool {
+        (self.scroll_top..=self.scroll_bottom).contains(&self.cursor.row)
+            && (self.scroll_left..=self.scroll_right).contains(&self.cursor.col)
+    }
+
+    /// Get the last column the cursor can print or move to
+    ///
+    /// This is the right margin, unless the cursor is already past it.
+    fn right_edge(&mut self) -> usize {
+        if self.cursor.col <= self.scroll_right {
+            self.scroll_right
+        } else {
+            self.grid().cols() - 1
+        }
+    }
+
+    /// Get the first column the cursor can move back to
+    ///
+    /// This is the left margin, unless the cursor is already before it.
+    fn left_edge(&self) -> usize {
+        if self.cursor.col >= self.scroll_left {
+            self.scroll_left
+        } else {
+            0
+        }
+    }
+
+    /// Scroll the scrolling region up by n lines
+    ///
+    /// Only a full-screen region feeds the scrollback buffer.
+    fn scroll_up(&mut self, n: usize) {
+        if self.has_horizontal_margins() {
+            let rect = self.margin_rect();
+            self.grid().scroll_rect_up(rect, n);
+        } else if self.is_full_scroll_region() {
+            self.grid().scroll_up(n);
+        } else {
+            let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+            self.grid().scroll_region_up(top, bottom, n);
+        }
+    }
+
+    /// Scroll the scrolling region down by n lines
+    fn scroll_down(&mut self, n: usize) {
+        if self.has_horizontal_margins() {
+            let rect = self.margin_rect();
+            self.grid().scroll_rect_down(rect, n);
+        } else {
+            let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+            self.grid().scroll_region_down(top, bottom, n);
+        }
+    }
+
+    /// Move the cursor down one line, scrolling at the bottom margin
+    fn linefeed(&mut self) {
+        self.wrap_pending = false;
+        let rows = self.grid().rows();
+        if self.cursor.row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.cursor.row + 1 < rows {
+            self.cursor.row += 1;
+        }
+    }
+
+    /// Move the cursor up one line, scrolling at the top margin
+    fn reverse_index(&mut self) {
+        self.wrap_pending = false;
+        if self.cursor.row == self.scroll_top {
+            self.scroll_down(1);
+        } else if self.cursor.row > 0 {
+            self.cursor.row -= 1;
+        }
+    }
+
+    /// Move the cursor to the left margin, or to the first column if it is
+    /// before the margin
+    fn carriage_return(&mut self) {
+        self.cursor.col = self.left_edge();
+        self.wrap_pending = false;
+    }
+
+    /// Move the cursor to an absolute position
+    ///
+    /// In origin mode the position is relative to the margins and the cursor
+    /// cannot leave them.
+    fn goto(&mut self, col: usize, row: usize) {
+        self.goto_row(row);
+        self.goto_col(col);
+    }
+
+    /// Move the cursor to an absolute column on the current row
+    fn goto_col(&mut self, col: usize) {
+        let (min_col, max_col) = if self.modes.is_origin_mode() {
+            (self.scroll_left, self.scroll_right)
+        } else {
+            (0, self.grid().cols() - 1)
+        };
+        self.cursor.goto_col((min_col + col).min(max_col));
+        self.wrap_pending = false;
+    }
+
+    /// Move the cursor to an absolute row in the current column
+    fn goto_row(&mut self, row: usize) {
+        let (min_row, max_row) = if self.modes.is_origin_mode() {
+            (self.scroll_top, self.scroll_bottom)
+        } else {
+            (0, self.grid().rows() - 1)
+        };
+        self.cursor.goto_row((min_row + row).min(max_row));
+        self.wrap_pending = false;
+    }
+
+    /// Move the cursor up n lines
+    ///
+    /// The cursor stops at the top margin when it starts inside the
+    /// scrolling region, and at the top of the screen otherwise.
+    fn move_up(&mut self, n: usize) {
+        let min_row = if self.cursor.row >= self.scroll_top { self.scroll_top } else { 0 };
+        self.cursor.row = self.cursor.row.saturating_sub(n).max(min_row);
+        self.wrap_pending = false;
+    }
+
+    /// Move the cursor down n lines
+    ///
+    /// The cursor stops at the bottom margin when it starts inside the
+    /// scrolling region, and at the bottom of the screen otherwise.
+    fn move_down(&mut self, n: usize) {
+        let max_row = if self.cursor.row <= self.scroll_bottom {
+            self.scroll_bottom
+        } else {
+            self.grid().rows() - 1
+        };
+        self.cursor.row = (self.cursor.row + n).min(max_row);
+        self.wrap_pending = false;
+    }
+
+    /// Move the cursor left n columns, stopping at the left margin
+    fn move_left(&mut self, n: usize) {
+        let min_col = self.left_edge();
+        self.cursor.col = self.cursor.col.saturating_sub(n).max(min_col);
+        self.wrap_pending = false;
+    }
+
+    /// Move the cursor right n columns, stopping at the right margin
+    fn move_right(&mut self, n: usize) {
+        let max_col = self.right_edge();
+        self.cursor.col = (self.cursor.col + n).min(max_col);
+        self.wrap_pending = false;
+    }
+
+    /// Set the scrolling region (DECSTBM) from 1-indexed margins
+    ///
+    /// A bottom margin of 0 means the last line. Invalid regions (the top
+    /// margin not above the bottom one) are ignored.
+    fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let rows = self.grid().rows();
+        let top = top.max(1);
+        let bottom = if bottom == 0 { rows } else { bottom.min(rows) };
+        if top >= bottom {
+            debug!("Ignoring invalid scroll region: {}..{}", top, bottom);
+            return;
+        }
+
+        self.scroll_top = top - 1;
+        self.scroll_bottom = bottom - 1;
+        // DECSTBM homes the cursor
+        self.goto(0, 0);
+    }
+
+    /// Set the left and right margins (DECSLRM) from 1-indexed columns
+    ///
+    /// A right margin of 0 means the last column. Invalid margins (the left
+    /// one not before the right one) are ignored.
+    fn set_horizontal_margins(&mut self, left: usize, right: usize) {
+        let cols = self.grid().cols();
+        let left = left.max(1);
+        let right = if right == 0 { cols } else { right.min(cols) };
+        if left >= right {
+            debug!("Ignoring invalid margins: {}..{}", left, right);
+            return;
+        }
+
+        self.scroll_left = left - 1;
+        self.scroll_right = right - 1;
+        // DECSLRM homes the cursor
+        self.goto(0, 0);
+    }
+
+    /// Reset the left and right margins to the full width of the screen
+    fn reset_horizontal_margins(&mut self) {
+        self.scroll_left = 0;
+        self.scroll_right = self.grid().cols() - 1;
+    }
+}
+
+/// Join OSC parameters back into text, dropping control characters
+///
+/// The parser splits OSC strings at `;`, which may legitimately appear in
+/// titles.
+fn osc_text(params: &[&[u8]]) -> String {
+    let text = params.join(&b';');
+    String::from_utf8_lossy(&text).chars().filter(|c| !c.is_control()).collect()
+}
+
+/// Parse a decimal OSC parameter
+fn parse_number(param: &[u8]) -> Option<u16> {
+    std::str::from_utf8(param).ok()?.parse().ok()
+}
+
+/// Parse the color of an extended color SGR parameter (38, 48, 58)
+///
+/// Accepts the colon form, where the components are subparameters of
+/// `param` (`58:5:n`, `58:2:r:g:b` or `58:2::r:g:b` with a color space id),
+/// and the semicolon form, where they follow as separate parameters.
+fn parse_sgr_color(param: &[u16], params: &mut ParamsIter<'_>) -> Option<Color> {
+    let components: Vec<u16> = if param.len() > 1 {
+        param[1..].to_vec()
+    } else {
+        let kind = *params.next()?.first()?;
+        // An unknown kind leaves the following parameters alone
+        let count = match kind {
+            2 => 3,
+            5 => 1,
+            _ => 0,
+        };
+        std::iter::once(kind).chain(params.take(count).filter_map(|p| p.first().copied())).collect()
+    };
+
+    let to_u8 = |value: &u16| u8::try_from(*value).ok();
+    match components.as_slice() {
+        [5, index] => to_u8(index).map(Color::from_ansi),
+        [2, _, r, g, b] | [2, r, g, b] => Some(Color::Rgb(to_u8(r)?, to_u8(g)?, to_u8(b)?)),
+        _ => {
+            debug!("Malformed extended color: {:?}", components);
+            None
+        }
+    }
+}
+
+/// Encode a color as an SGR parameter for DECRQSS replies
+///
+/// `extended` is the extended color code (38, 48 or 58). Default colors need
+/// no parameter.
+fn sgr_color_param(color: Color, extended: u16) -> Option<String> {
+    match color {
+        Color::Named(NamedColor::Foreground | NamedColor::Background) => None,
+        // Foreground and background have short forms for the 16 named
+        // colors (30-37/90-97 and 40-47/100-107); SGR 58 does not
+        Color::Named(named) if extended != 58 => {
+            let index = named as u16;
+            let base = extended - 8;
+            Some(if index < 8 { base + index } else { base + 60 + index - 8 }.to_string())
+        }
+        Color::Named(named) => Some(format!("{}:5:{}", extended, named as u16)),
+        Color::Indexed(index) => Some(format!("{}:5:{}", extended, index)),
+        Color::Rgb(r, g, b) => Some(format!("{}:2::{}:{}:{}", extended, r, g, b)),
+    }
+}
+
+/// Encode a mode state for a DECRPM reply
+///
+/// 0 = not recognized, 1 = set, 2 = reset.
+fn mode_report_value(state: Option<bool>) -> u8 {
+    match state {
+        None => 0,
+        Some(true) => 1,
+        Some(false) => 2,
+    }
+}
+
+/// Get the numeric value of the parameter at `index`, if present
+fn param(params: &Params, index: usize) -> Option<u16> {
+    params.iter().nth(index).and_then(|p| p.first()).copied()
+}
+
+/// Get a 1-indexed position parameter as a 0-indexed value
+///
+/// Missing and zero parameters select the default, so they yield None.
+fn position_param(params: &Params, index: usize) -> Option<usize> {
+    param(params, index).filter(|&n| n > 0).map(|n| n as usize - 1)
 }
 
 impl Perform for TerminalState {
     fn print(&mut self, c: char) {
         trace!("Print: {:?}", c);
 
-        // Get the current cursor position
-        let col = self.cursor.col;
+        // Zero-width characters (combining marks, variation selectors, zero
+        // width joiners) extend the grapheme cluster of the previous cell
+        if c.width() == Some(0) {
+            self.push_zerowidth(c);
+            return;
+        }
+        let c = self.charsets.translate(c);
+        self.last_char = Some(c);
+
         let cols = self.grid().cols();
-        let rows = self.grid().rows();
+        // Soft wraps are only recorded for lines that span the whole screen
+        let marks_wraps = !self.has_horizontal_margins();
 
-        // Check if we need to wrap to the next line
-        if col >= cols {
-            if self.modes.is_auto_wrap() {
-                self.cursor.col = 0;
-                self.cursor.row += 1;
+        // Wrap to the next line if the previous character filled the last column
+        if self.wrap_pending && self.modes.is_auto_wrap() {
+            let row = self.cursor.row;
+            if marks_wraps {
+                if let Some(cell) = self.grid().get_mut(cols - 1, row) {
+                    cell.flags.insert(CellFlags::WRAPLINE);
+                }
+            }
+            self.carriage_return();
+            // Scroll if we're at the bottom margin
+            self.linefeed();
+        }
+        self.wrap_pending = false;
+
+        let width = if cols < 2 { 1 } else { c.width().unwrap_or(1).clamp(1, 2) };
+        let right = self.right_edge();
 
-                // Scroll if we're at the bottom
-                if self.cursor.row >= rows {
-                    self.grid().scroll_up(1);
-                    self.cursor.row = rows - 1;
+        // A wide character that does not fit before the right margin wraps
+        // early, leaving a padding spacer behind
+        if width == 2 && self.cursor.col >= right {
+            if self.modes.is_auto_wrap() {
+                let row = self.cursor.row;
+                self.grid().clear_wide_pair(right, row);
+                let blank = self.grid().blank();
+                if let Some(cell) = self.grid().get_mut(right, row) {
+                    *cell = blank;
+                    cell.flags.insert(CellFlags::WIDE_CHAR_SPACER);
+                    if marks_wraps {
+                        cell.flags.insert(CellFlags::WRAPLINE);
+                    }
                 }
+                self.carriage_return();
+                self.linefeed();
             } else {
-                // No wrap - just stay at the end
-                self.cursor.col = cols - 1;
+                self.cursor.col = right - 1;
             }
         }
+        let right = self.right_edge();
 
-        // Create a cell with the current attributes
-        let mut cell = super::cell::Cell::with_colors(c, self.current_fg, self.current_bg);
-        cell.flags = self.current_flags;
+        let mut cell = self.pen(c);
 
         // Store cursor position before borrowing grid
         let cursor_col = self.cursor.col;
         let cursor_row = self.cursor.row;
-        
-        // Set the cell in the grid
+
+        // Insert mode (IRM) shifts the rest of the line right; cells pushed
+        // past the right margin are lost
+        if self.modes.is_insert_mode() {
+            self.grid().insert_cells(cursor_col, cursor_row, right, width);
+        }
+
+        // Overwriting half of a wide character erases the other half
+        for col in cursor_col..cursor_col + width {
+            self.grid().clear_wide_pair(col, cursor_row);
+        }
+
+        // Set the cell in the grid, followed by a spacer for wide characters
+        if width == 2 {
+            let spacer = Cell { c: ' ', flags: cell.flags | CellFlags::WIDE_CHAR_SPACER, ..cell };
+            cell.flags.insert(CellFlags::WIDE_CHAR);
+            self.grid().set(cursor_col + 1, cursor_row, spacer);
+        }
         self.grid().set(cursor_col, cursor_row, cell);
 
-        // Advance cursor
-        self.cursor.col += 1;
+        // Advance cursor; at the right margin the wrap is deferred until the
+        // next character so that the cursor never leaves the screen
+        if cursor_col + width <= right {
+            self.cursor.col += width;
+        } else {
+            self.cursor.col = right;
+            self.wrap_pending = self.modes.is_auto_wrap();
+        }
     }
 
     fn execute(&mut self, byte: u8) {
         trace!("Execute: 0x{:02X}", byte);
 
         match byte {
-            // Line feed
-            0x0A => {
-                let rows = self.grid().rows();
-                self.cursor.row += 1;
-                if self.cursor.row >= rows {
-                    self.grid().scroll_up(1);
-                    self.cursor.row = rows - 1;
-                }
+            // Line feed, vertical tab and form feed
+            0x0A..=0x0C => {
+                self.linefeed();
             }
             // Carriage return
-            0x0D => {
-                self.cursor.col = 0;
-            }
+            0x0D => self.carriage_return(),
             // Backspace
-            0x08 => {
-                if self.cursor.col > 0 {
-                    self.cursor.col -= 1;
-                }
-            }
+            0x08 => self.move_left(1),
             // Tab
             0x09 => {
-                let cols = self.grid().cols();
-                self.cursor.col = (self.cursor.col + 8) & !7;
-                if self.cursor.col >= cols {
-                    self.cursor.col = cols - 1;
-                }
+                let right = self.right_edge();
+                self.cursor.col = self.tab_stops.next(self.cursor.col).min(right);
+                self.wrap_pending = false;
             }
+            // SO - Shift Out (invoke G1)
+            0x0E => self.charsets.lock_shift(1),
+            // SI - Shift In (invoke G0)
+            0x0F => self.charsets.lock_shift(0),
             _ => {
                 debug!("Unhandled execute: 0x{:02X}", byte);
             }
         }
     }
 
-    fn hook(&mut self, _params: &Params, _intermediates: &[u8], _ignore: bool, _action: char) {
-        trace!("Hook");
+    fn hook(&mut self, params: &Params, intermediates: &[u8], _ignore: bool, action: char) {
+        trace!("Hook: {:?} {:?} {}", params, intermediates, action);
+
+        self.decrqss = None;
+        match (intermediates, action) {
+            // DECRQSS - Request Selection or Setting
+            ([b'$'], 'q') => self.decrqss = Some(Vec::new()),
+            _ => debug!("Unhandled DCS: {:?} {:?} {}", params, intermediates, action),
+        }
     }
 
-    fn put(&mut self, _byte: u8) {
-        trace!("Put");
+    fn put(&mut self, byte: u8) {
+        trace!("Put: 0x{:02X}", byte);
+
+        if let Some(request) = &mut self.decrqss {
+            if request.len() < MAX_DECRQSS_LEN {
+                request.push(byte);
+            }
+        }
     }
 
     fn unhook(&mut self) {
         trace!("Unhook");
+
+        if let Some(request) = self.decrqss.take() {
+            self.report_setting(&request);
+        }
     }
 
-    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+    fn osc_dispatch(&mut self, params: &[&[u8]], bell_terminated: bool) {
         trace!("OSC dispatch: {:?}", params);
-        // OSC sequences (Operating System Commands) will be handled here
-        // Examples: window title, color changes, hyperlinks
+
+        let Some(&command) = params.first() else {
+            return;
+        };
+
+        // Replies use the same terminator as the request
+        let terminator = if bell_terminated { "\x07" } else { "\x1b\\" };
+
+        match command {
+            // Set icon name and window title
+            b"0" => {
+                let title = osc_text(&params[1..]);
+                self.icon_name = Some(title.clone());
+                self.title = Some(title);
+            }
+            // Set icon name
+            b"1" => self.icon_name = Some(osc_text(&params[1..])),
+            // Set window title
+            b"2" => self.title = Some(osc_text(&params[1..])),
+            // Hyperlink
+            b"8" if params.len() >= 3 => self.set_hyperlink(params[1], &params[2..]),
+            // Clipboard access
+            b"52" if params.len() >= 3 => self.handle_clipboard(params[1], params[2], terminator),
+            // Set or query indexed colors
+            b"4" => self.set_indexed_colors(&params[1..], terminator),
+            // Set or query foreground, background, cursor and selection colors
+            b"10" | b"11" | b"12" | b"17" | b"19" => {
+                if let Some(number) = parse_number(command) {
+                    self.set_special_colors(number, &params[1..], terminator);
+                }
+            }
+            // Desktop notifications
+            b"9" => {
+                if let Some(notification) = Notification::from_osc9(&params[1..]) {
+                    self.notifications.push(notification);
+                }
+            }
+            b"99" if params.len() >= 3 => {
+                let payload = params[2..].join(&b';');
+                if let Some(notification) = self.kitty_notifications.push(params[1], &payload) {
+                    self.notifications.push(notification);
+                }
+            }
+            b"777" => {
+                if let Some(notification) = Notification::from_osc777(&params[1..]) {
+                    self.notifications.push(notification);
+                }
+            }
+            // Current working directory
+            b"7" if params.len() >= 2 => {
+                let uri = params[1..].join(&b';');
+                match cwd::parse_file_uri(&uri, cwd::local_hostname()) {
+                    Some(ReportedDirectory::Local(path)) => self.working_directory = Some(path),
+                    // The last local directory is stale, e.g. inside an SSH session
+                    Some(ReportedDirectory::Remote) => self.working_directory = None,
+                    None => debug!("Ignoring OSC 7 URI {:?}", String::from_utf8_lossy(&uri)),
+                }
+            }
+            // Shell integration (FinalTerm semantic prompts)
+            b"133" => match SemanticMark::from_osc(&params[1..]) {
+                Some(mark) => {
+                    let (col, row) = (self.cursor.col, self.cursor.row);
+                    self.grid().add_mark(col, row, mark);
+                }
+                None => debug!("Unhandled OSC 133: {:?}", params),
+            },
+            // Reset indexed colors
+            b"104" => self.reset_indexed_colors(&params[1..]),
+            // Reset special colors
+            b"110" => self.reset_special_color(SpecialColor::Foreground),
+            b"111" => self.reset_special_color(SpecialColor::Background),
+            b"112" => self.reset_special_color(SpecialColor::Cursor),
+            b"117" => self.reset_special_color(SpecialColor::SelectionBackground),
+            b"119" => self.reset_special_color(SpecialColor::SelectionForeground),
+            _ => {
+                debug!("Unhandled OSC: {:?}", String::from_utf8_lossy(command));
+            }
+        }
     }
 
     fn csi_dispatch(
@@ -259,51 +1400,65 @@ impl Perform for TerminalState {
     ) {
         trace!("CSI dispatch: {:?} {}", params, action);
 
-        // Handle private mode sequences (DEC codes)
-        if !intermediates.is_empty() && intermediates[0] == b'?' {
-            self.handle_decset(params, action);
+        // Handle private mode sequences (DEC codes) and other intermediates
+        if !intermediates.is_empty() {
+            self.handle_intermediate_csi(params, intermediates, action);
             return;
         }
 
         match action {
             // Cursor movement
             'A' => {
-                // Cursor Up
-                let n = params.iter().next().and_then(|p| p.first()).copied().unwrap_or(1) as usize;
-                self.cursor.row = self.cursor.row.saturating_sub(n);
-            }
-            'B' => {
-                // Cursor Down
-                let n = params.iter().next().and_then(|p| p.first()).copied().unwrap_or(1) as usize;
-                let max_row = self.grid().rows() - 1;
-                self.cursor.row = (self.cursor.row + n).min(max_row);
-            }
-            'C' => {
-                // Cursor Forward
-                let n = params.iter().next().and_then(|p| p.first()).copied().unwrap_or(1) as usize;
-                let max_col = self.grid().cols() - 1;
-                self.cursor.col = (self.cursor.col + n).min(max_col);
+                // CUU - Cursor Up
+                let n = param(params, 0).unwrap_or(1).max(1) as usize;
+                self.move_up(n);
+            }
+            'B' | 'e' => {
+                // CUD - Cursor Down / VPR - Line Position Forward
+                let n = param(params, 0).unwrap_or(1).max(1) as usize;
+                self.move_down(n);
+            }
+            'C' | 'a' => {
+                // CUF - Cursor Forward / HPR - Character Position Forward
+                let n = param(params, 0).unwrap_or(1).max(1) as usize;
+                self.move_right(n);
             }
             'D' => {
-                // Cursor Back
-                let n = params.iter().next().and_then(|p| p.first()).copied().unwrap_or(1) as usize;
-                self.cursor.col = self.cursor.col.saturating_sub(n);
+                // CUB - Cursor Back
+                let n = param(params, 0).unwrap_or(1).max(1) as usize;
+                self.move_left(n);
+            }
+            'E' => {
+                // CNL - Cursor Next Line
+                let n = param(params, 0).unwrap_or(1).max(1) as usize;
+                self.move_down(n);
+                self.carriage_return();
+            }
+            'F' => {
+                // CPL - Cursor Previous Line
+                let n = param(params, 0).unwrap_or(1).max(1) as usize;
+                self.move_up(n);
+                self.carriage_return();
+            }
+            'G' | '`' => {
+                // CHA - Cursor Horizontal Absolute / HPA - Character Position Absolute
+                let col = param(params, 0).unwrap_or(1).max(1) as usize;
+                self.goto_col(col - 1);
+            }
+            'd' => {
+                // VPA - Line Position Absolute
+                let row = param(params, 0).unwrap_or(1).max(1) as usize;
+                self.goto_row(row - 1);
             }
             'H' | 'f' => {
-                // Cursor Position
-                let mut iter = params.iter();
-                let row =
-                    iter.next().and_then(|p| p.first()).copied().unwrap_or(1).saturating_sub(1)
-                        as usize;
-                let col =
-                    iter.next().and_then(|p| p.first()).copied().unwrap_or(1).saturating_sub(1)
-                        as usize;
-                let max_col = self.grid().cols() - 1;
-                let max_row = self.grid().rows() - 1;
-                self.cursor.goto(col.min(max_col), row.min(max_row));
+                // CUP - Cursor Position / HVP - Horizontal and Vertical Position
+                let row = param(params, 0).unwrap_or(1).max(1) as usize;
+                let col = param(params, 1).unwrap_or(1).max(1) as usize;
+                self.goto(col - 1, row - 1);
             }
             'J' => {
                 // Erase in Display
+                self.wrap_pending = false;
                 let n = params.iter().next().and_then(|p| p.first()).copied().unwrap_or(0);
                 let cursor_col = self.cursor.col;
                 let cursor_row = self.cursor.row;
@@ -317,10 +1472,11 @@ impl Perform for TerminalState {
                         }
                     }
                     1 => {
-                        // Clear from cursor to beginning of screen
+                        // Clear from beginning of screen through the cursor
                         for row in 0..cursor_row {
                             self.grid().clear_row(row);
                         }
+                        self.grid().erase_cells(0, cursor_row, cursor_col + 1);
                     }
                     2 => {
                         // Clear entire screen
@@ -331,6 +1487,7 @@ impl Perform for TerminalState {
             }
             'K' => {
                 // Erase in Line
+                self.wrap_pending = false;
                 let n = params.iter().next().and_then(|p| p.first()).copied().unwrap_or(0);
                 let cursor_col = self.cursor.col;
                 let cursor_row = self.cursor.row;
@@ -341,11 +1498,7 @@ impl Perform for TerminalState {
                     }
                     1 => {
                         // Clear from cursor to beginning of line
-                        for col in 0..=cursor_col {
-                            if let Some(cell) = self.grid().get_mut(col, cursor_row) {
-                                cell.reset();
-                            }
-                        }
+                        self.grid().erase_cells(0, cursor_row, cursor_col + 1);
                     }
                     2 => {
                         // Clear entire line
@@ -354,9 +1507,144 @@ impl Perform for TerminalState {
                     _ => {}
                 }
             }
+            '@' => {
+                // ICH - Insert Character
+                self.wrap_pending = false;
+                let n = param(params, 0).unwrap_or(1).max(1) as usize;
+                let (col, row) = (self.cursor.col, self.cursor.row);
+                // Outside the margins the line is left alone
+                if (self.scroll_left..=self.scroll_right).contains(&col) {
+                    let right = self.scroll_right;
+                    self.grid().insert_cells(col, row, right, n);
+                }
+            }
+            'P' => {
+                // DCH - Delete Character
+                self.wrap_pending = false;
+                let n = param(params, 0).unwrap_or(1).max(1) as usize;
+                let (col, row) = (self.cursor.col, self.cursor.row);
+                // Outside the margins the line is left alone
+                if (self.scroll_left..=self.scroll_right).contains(&col) {
+                    let right = self.scroll_right;
+                    self.grid().delete_cells(col, row, right, n);
+                }
+            }
+            'X' => {
+                // ECH - Erase Character
+                self.wrap_pending = false;
+                let n = param(params, 0).unwrap_or(1).max(1) as usize;
+                let col = self.cursor.col;
+                let row = self.cursor.row;
+                self.grid().erase_cells(col, row, n);
+            }
+            'L' => {
+                // IL - Insert Line
+                let n = param(params, 0).unwrap_or(1).max(1) as usize;
+                self.insert_lines(n);
+            }
+            'M' => {
+                // DL - Delete Line
+                let n = param(params, 0).unwrap_or(1).max(1) as usize;
+                self.delete_lines(n);
+            }
+            'b' => {
+                // REP - Repeat preceding graphic character
+                let n = param(params, 0).unwrap_or(1).max(1);
+                if let Some(c) = self.last_char {
+                    for _ in 0..n {
+                        self.print(c);
+                    }
+                }
+            }
+            'I' => {
+                // CHT - Cursor Horizontal Forward Tabulation
+                let n = param(params, 0).unwrap_or(1).max(1);
+                let right = self.right_edge();
+                for _ in 0..n {
+                    self.cursor.col = self.tab_stops.next(self.cursor.col).min(right);
+                }
+                self.wrap_pending = false;
+            }
+            'Z' => {
+                // CBT - Cursor Backward Tabulation
+                let n = param(params, 0).unwrap_or(1).max(1);
+                let left = self.left_edge();
+                for _ in 0..n {
+                    self.cursor.col = self.tab_stops.prev(self.cursor.col).max(left);
+                }
+                self.wrap_pending = false;
+            }
+            'g' => {
+                // TBC - Tab Clear
+                match param(params, 0).unwrap_or(0) {
+                    0 => {
+                        let col = self.cursor.col;
+                        self.tab_stops.clear(col);
+                    }
+                    3 => self.tab_stops.clear_all(),
+                    n => debug!("Unhandled TBC: {}", n),
+                }
+            }
+            'c' => {
+                // DA1 - Primary Device Attributes
+                if param(params, 0).unwrap_or(0) == 0 {
+                    self.respond(PRIMARY_DEVICE_ATTRIBUTES);
+                }
+            }
+            'n' => {
+                // DSR - Device Status Report
+                match param(params, 0).unwrap_or(0) {
+                    5 => self.respond(b"\x1b[0n"),
+                    6 => {
+                        let (col, row) = self.report_position();
+                        self.respond(format!("\x1b[{};{}R", row, col).as_bytes());
+                    }
+                    n => debug!("Unhandled DSR: {}", n),
+                }
+            }
+            't' => {
+                // XTWINOPS - Window manipulation (title stack only)
+                let which = param(params, 1).unwrap_or(0);
+                match param(params, 0).unwrap_or(0) {
+                    22 => self.push_title(which),
+                    23 => self.pop_title(which),
+                    n => debug!("Unhandled XTWINOPS: {}", n),
+                }
+            }
+            'r' => {
+                // DECSTBM - Set Top and Bottom Margins
+                let top = param(params, 0).unwrap_or(1) as usize;
+                let bottom = param(params, 1).unwrap_or(0) as usize;
+                self.set_scroll_region(top, bottom);
+            }
+            's' if self.modes.is_left_right_margin() => {
+                // DECSLRM - Set Left and Right Margins
+                let left = param(params, 0).unwrap_or(1) as usize;
+                let right = param(params, 1).unwrap_or(0) as usize;
+                self.set_horizontal_margins(left, right);
+            }
+            's' => {
+                // SCOSC - Save Cursor (same state as DECSC)
+                self.save_cursor();
+            }
+            'u' => {
+                // SCORC - Restore Cursor
+                self.restore_cursor();
+            }
+            'S' => {
+                // SU - Scroll Up
+                let n = param(params, 0).unwrap_or(1).max(1) as usize;
+                self.scroll_up(n);
+            }
+            'T' => {
+                // SD - Scroll Down
+                let n = param(params, 0).unwrap_or(1).max(1) as usize;
+                self.scroll_down(n);
+            }
             'm' => {
                 // SGR - Select Graphic Rendition (colors and attributes)
                 self.handle_sgr(params);
+                self.sync_background();
             }
             'h' | 'l' => {
                 // Set/Reset mode (non-private)
@@ -368,96 +1656,365 @@ impl Perform for TerminalState {
         }
     }
 
-    fn esc_dispatch(&mut self, _intermediates: &[u8], _ignore: bool, _byte: u8) {
-        trace!("ESC dispatch");
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        trace!("ESC dispatch: {:?} {}", intermediates, byte as char);
+
+        match (intermediates, byte) {
+            // DECSC - Save Cursor
+            ([], b'7') => self.save_cursor(),
+            // DECRC - Restore Cursor
+            ([], b'8') => self.restore_cursor(),
+            // IND - Index
+            ([], b'D') => self.linefeed(),
+            // NEL - Next Line
+            ([], b'E') => {
+                self.carriage_return();
+                self.linefeed();
+            }
+            // HTS - Horizontal Tab Set
+            ([], b'H') => {
+                self.tab_stops.set(self.cursor.col);
+            }
+            // RI - Reverse Index
+            ([], b'M') => self.reverse_index(),
+            // RIS - Reset to Initial State
+            ([], b'c') => self.reset(),
+            // DECKPAM - Application Keypad
+            ([], b'=') => self.modes.insert(TerminalModes::KEYPAD_APP),
+            // DECKPNM - Normal Keypad
+            ([], b'>') => self.modes.remove(TerminalModes::KEYPAD_APP),
+            // DECALN - Screen Alignment Pattern
+            ([b'#'], b'8') => self.screen_alignment_test(),
+            // SS2/SS3 - Single Shift 2/3
+            ([], b'N') => self.charsets.single_shift(2),
+            ([], b'O') => self.charsets.single_shift(3),
+            // LS2/LS3 - Locking Shift 2/3
+            ([], b'n') => self.charsets.lock_shift(2),
+            ([], b'o') => self.charsets.lock_shift(3),
+            // SCS - Designate G0-G3 character set
+            ([slot @ (b'(' | b')' | b'*' | b'+')], _) => {
+                let index = (slot - b'(') as usize;
+                match Charset::from_designator(byte) {
+                    Some(charset) => self.charsets.designate(index, charset),
+                    None => debug!("Unsupported charset: G{} {}", index, byte as char),
+                }
+            }
+            _ => {
+                debug!("Unhandled ESC: {:?} {}", intermediates, byte as char);
+            }
+        }
+    }
+}
+
+impl TerminalState {
+    /// Get the area rectangular operations can address
+    ///
+    /// In origin mode this is the area inside the margins.
+    fn addressable_area(&mut self) -> Rect {
+        if self.modes.is_origin_mode() {
+            self.margin_rect()
+        } else {
+            Rect::new(0, 0, self.grid().rows() - 1, self.grid().cols() - 1)
+        }
+    }
+
+    /// Read a rectangle from the four 1-indexed parameters `Pt;Pl;Pb;Pr`
+    /// starting at `index`
+    ///
+    /// Missing or zero parameters default to the edges of the addressable
+    /// area, which also clips the rectangle. The result may be empty, which
+    /// the grid ignores.
+    fn rect_param(&mut self, params: &Params, index: usize) -> Rect {
+        let area = self.addressable_area();
+        let value = |offset| position_param(params, index + offset);
+        Rect::new(
+            area.top + value(0).unwrap_or(0),
+            area.left + value(1).unwrap_or(0),
+            value(2).map_or(area.bottom, |n| (area.top + n).min(area.bottom)),
+            value(3).map_or(area.right, |n| (area.left + n).min(area.right)),
+        )
+    }
+
+    /// Fill a rectangle with a character drawn with the current pen (DECFRA)
+    fn fill_rect_area(&mut self, params: &Params) {
+        let ch = param(params, 0).unwrap_or(0);
+        // Only printable characters of the GL and GR ranges are allowed
+        let Some(c) =
+            u8::try_from(ch).ok().filter(|c| matches!(c, 32..=126 | 160..=255)).map(char::from)
+        else {
+            debug!("Ignoring DECFRA with character {}", ch);
+            return;
+        };
+        let rect = self.rect_param(params, 1);
+        let cell = self.pen(c);
+        self.grid().fill_rect(rect, cell);
+    }
+
+    /// Copy a rectangle to another position (DECCRA)
+    ///
+    /// The parameters are the source rectangle, its page, the destination
+    /// top left corner and its page. There is only one page, so the page
+    /// numbers are ignored.
+    fn copy_rect_area(&mut self, params: &Params) {
+        let src = self.rect_param(params, 0);
+        let area = self.addressable_area();
+        let top = area.top + position_param(params, 5).unwrap_or(0);
+        let left = area.left + position_param(params, 6).unwrap_or(0);
+        if top > area.bottom || left > area.right {
+            return;
+        }
+        // The destination is clipped by the addressable area too
+        let src = Rect {
+            bottom: src.bottom.min(src.top + (area.bottom - top)),
+            right: src.right.min(src.left + (area.right - left)),
+            ..src
+        };
+        self.grid().copy_rect(src, top, left);
+    }
+
+    /// Change the attributes of a rectangle or a stream of cells (DECCARA)
+    ///
+    /// Bold, underline, blink, inverse and invisible can be set (1, 4, 5, 7,
+    /// 8) or cleared (22, 24, 25, 27, 28); 0 clears all of them. DECSACE
+    /// selects whether the area is a rectangle or runs through whole lines
+    /// from the first position to the last.
+    fn change_rect_attributes(&mut self, params: &Params) {
+        let rect = self.rect_param(params, 0);
+        let (mut set, mut clear) = (CellFlags::empty(), CellFlags::empty());
+        let mut change = |mask: CellFlags, flag: CellFlags| {
+            clear.insert(mask);
+            set = set.difference(mask).union(flag);
+        };
+        let none = CellFlags::empty();
+        for param in params.iter().skip(4) {
+            match param.first().copied().unwrap_or(0) {
+                0 => change(
+                    CellFlags::BOLD
+                        | CellFlags::ALL_UNDERLINES
+                        | CellFlags::BLINK
+                        | CellFlags::INVERSE
+                        | CellFlags::HIDDEN,
+                    none,
+                ),
+                1 => change(CellFlags::BOLD, CellFlags::BOLD),
+                4 => change(CellFlags::ALL_UNDERLINES, CellFlags::UNDERLINE),
+                5 => change(CellFlags::BLINK, CellFlags::BLINK),
+                7 => change(CellFlags::INVERSE, CellFlags::INVERSE),
+                8 => change(CellFlags::HIDDEN, CellFlags::HIDDEN),
+                22 => change(CellFlags::BOLD, none),
+                24 => change(CellFlags::ALL_UNDERLINES, none),
+                25 => change(CellFlags::BLINK, none),
+                27 => change(CellFlags::INVERSE, none),
+                28 => change(CellFlags::HIDDEN, none),
+                n => debug!("Unsupported DECCARA attribute: {}", n),
+            }
+        }
+
+        if self.rectangular_attribute_extent || rect.top >= rect.bottom {
+            self.grid().change_attributes(rect, set, clear);
+            return;
+        }
+        let cols = self.grid().cols();
+        for row in rect.top..=rect.bottom {
+            let left = if row == rect.top { rect.left } else { 0 };
+            let right = if row == rect.bottom { rect.right } else { cols - 1 };
+            self.grid().change_attributes(Rect::new(row, left, row, right), set, clear);
+        }
     }
 }
 
 impl TerminalState {
+    /// Make both screens erase with the current background color (BCE)
+    fn sync_background(&mut self) {
+        self.primary_grid.set_background(self.current_bg);
+        self.alternate_grid.set_background(self.current_bg);
+    }
+
+    /// Reset all graphic rendition attributes (SGR 0)
+    fn reset_attributes(&mut self) {
+        self.current_fg = Color::default();
+        self.current_bg = Color::Named(super::color::NamedColor::Background);
+        self.current_flags = CellFlags::empty();
+        self.current_underline_color = None;
+    }
+
     /// Handle SGR (Select Graphic Rendition) sequences
+    ///
+    /// Extended colors (38, 48, 58) accept both the colon and the semicolon
+    /// form; malformed or out-of-range colors are ignored.
     fn handle_sgr(&mut self, params: &Params) {
         if params.is_empty() {
-            // Reset all attributes
-            self.current_fg = Color::default();
-            self.current_bg = Color::Named(super::color::NamedColor::Background);
-            self.current_flags = CellFlags::empty();
+            self.reset_attributes();
             return;
         }
 
         let mut iter = params.iter();
         while let Some(param) = iter.next() {
-            if let Some(&n) = param.first() {
-                match n {
-                    0 => {
-                        // Reset
-                        self.current_fg = Color::default();
-                        self.current_bg = Color::Named(super::color::NamedColor::Background);
-                        self.current_flags = CellFlags::empty();
-                    }
-                    1 => self.current_flags.insert(CellFlags::BOLD),
-                    2 => self.current_flags.insert(CellFlags::DIM),
-                    3 => self.current_flags.insert(CellFlags::ITALIC),
-                    4 => self.current_flags.insert(CellFlags::UNDERLINE),
-                    7 => self.current_flags.insert(CellFlags::INVERSE),
-                    8 => self.current_flags.insert(CellFlags::HIDDEN),
-                    9 => self.current_flags.insert(CellFlags::STRIKETHROUGH),
-                    22 => self.current_flags.remove(CellFlags::BOLD | CellFlags::DIM),
-                    23 => self.current_flags.remove(CellFlags::ITALIC),
-                    24 => self.current_flags.remove(CellFlags::UNDERLINE),
-                    27 => self.current_flags.remove(CellFlags::INVERSE),
-                    28 => self.current_flags.remove(CellFlags::HIDDEN),
-                    29 => self.current_flags.remove(CellFlags::STRIKETHROUGH),
-                    // Foreground colors (30-37, 90-97)
-                    30..=37 => self.current_fg = Color::from_ansi((n - 30) as u8),
-                    90..=97 => self.current_fg = Color::from_ansi((n - 90 + 8) as u8),
-                    // Background colors (40-47, 100-107)
-                    40..=47 => self.current_bg = Color::from_ansi((n - 40) as u8),
-                    100..=107 => self.current_bg = Color::from_ansi((n - 100 + 8) as u8),
-                    // Extended colors
-                    38 | 48 => {
-                        let is_fg = n == 38;
-                        if let Some(color_type) = iter.next().and_then(|p| p.first()) {
-                            match color_type {
-                                5 => {
-                                    // 256-color
-                                    if let Some(&idx) = iter.next().and_then(|p| p.first()) {
-                                        let color = Color::from_ansi(idx as u8);
-                                        if is_fg {
-                                            self.current_fg = color;
-                                        } else {
-                                            self.current_bg = color;
-                                        }
-                                    }
-                                }
-                                2 => {
-                                    // RGB color
-                                    let r =
-                                        iter.next().and_then(|p| p.first()).copied().unwrap_or(0)
-                                            as u8;
-                                    let g =
-                                        iter.next().and_then(|p| p.first()).copied().unwrap_or(0)
-                                            as u8;
-                                    let b =
-                                        iter.next().and_then(|p| p.first()).copied().unwrap_or(0)
-                                            as u8;
-                                    let color = Color::Rgb(r, g, b);
-                                    if is_fg {
-                                        self.current_fg = color;
-                                    } else {
-                                        self.current_bg = color;
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
+            let Some(&n) = param.first() else {
+                continue;
+            };
+            match n {
+                0 => self.reset_attributes(),
+                1 => self.current_flags.insert(CellFlags::BOLD),
+                2 => self.current_flags.insert(CellFlags::DIM),
+                3 => self.current_flags.insert(CellFlags::ITALIC),
+                4 => {
+                    // 4 alone is a single underline, 4:x selects a style
+                    let style = param.get(1).copied().unwrap_or(1);
+                    match UnderlineStyle::from_sgr(style) {
+                        Some(style) => self.current_flags.set_underline_style(style),
+                        None => debug!("Unknown underline style: {:?}", param),
                     }
-                    39 => self.current_fg = Color::default(), // Default foreground
-                    49 => self.current_bg = Color::Named(super::color::NamedColor::Background), // Default background
-                    _ => {
-                        debug!("Unhandled SGR parameter: {}", n);
+                }
+                // Slow and rapid blink are not distinguished
+                5 | 6 => self.current_flags.insert(CellFlags::BLINK),
+                7 => self.current_flags.insert(CellFlags::INVERSE),
+                8 => self.current_flags.insert(CellFlags::HIDDEN),
+                9 => self.current_flags.insert(CellFlags::STRIKETHROUGH),
+                21 => self.current_flags.set_underline_style(Some(UnderlineStyle::Double)),
+                22 => self.current_flags.remove(CellFlags::BOLD | CellFlags::DIM),
+                23 => self.current_flags.remove(CellFlags::ITALIC),
+                24 => self.current_flags.set_underline_style(None),
+                25 => self.current_flags.remove(CellFlags::BLINK),
+                27 => self.current_flags.remove(CellFlags::INVERSE),
+                28 => self.current_flags.remove(CellFlags::HIDDEN),
+                29 => self.current_flags.remove(CellFlags::STRIKETHROUGH),
+                30..=37 => self.current_fg = Color::from_ansi((n - 30) as u8),
+                38 => {
+                    if let Some(color) = parse_sgr_color(param, &mut iter) {
+                        self.current_fg = color;
+                    }
+                }
+                39 => self.current_fg = Color::default(),
+                40..=47 => self.current_bg = Color::from_ansi((n - 40) as u8),
+                48 => {
+                    if let Some(color) = parse_sgr_color(param, &mut iter) {
+                        self.current_bg = color;
                     }
                 }
+                49 => self.current_bg = Color::Named(super::color::NamedColor::Background),
+                53 => self.current_flags.insert(CellFlags::OVERLINE),
+                55 => self.current_flags.remove(CellFlags::OVERLINE),
+                58 => {
+                    if let Some(color) = parse_sgr_color(param, &mut iter) {
+                        self.current_underline_color = Some(color);
+                    }
+                }
+                59 => self.current_underline_color = None,
+                90..=97 => self.current_fg = Color::from_ansi((n - 90 + 8) as u8),
+                100..=107 => self.current_bg = Color::from_ansi((n - 100 + 8) as u8),
+                _ => debug!("Unhandled SGR parameter: {:?}", param),
+            }
+        }
+    }
+
+    /// Handle CSI sequences with private markers or intermediate bytes
+    fn handle_intermediate_csi(&mut self, params: &Params, intermediates: &[u8], action: char) {
+        match (intermediates, action) {
+            ([b'?'], 'h' | 'l') => self.handle_decset(params, action),
+            ([b'?'], 'n') => {
+                // DECXCPR - Extended Cursor Position Report
+                if param(params, 0) == Some(6) {
+                    let (col, row) = self.report_position();
+                    self.respond(format!("\x1b[?{};{};1R", row, col).as_bytes());
+                } else {
+                    debug!("Unhandled private DSR: {:?}", params);
+                }
+            }
+            ([b'>'], 'c') => {
+                // DA2 - Secondary Device Attributes
+                if param(params, 0).unwrap_or(0) == 0 {
+                    self.respond(SECONDARY_DEVICE_ATTRIBUTES);
+                }
+            }
+            ([b'?', b'$'], 'p') => {
+                // DECRQM - Request DEC private mode
+                let mode = param(params, 0).unwrap_or(0);
+                let state = mode_report_value(self.private_mode_state(mode));
+                self.respond(format!("\x1b[?{};{}$y", mode, state).as_bytes());
+            }
+            ([b' '], 'q') => {
+                // DECSCUSR - Set Cursor Style
+                let ps = param(params, 0).unwrap_or(0);
+                match CursorStyle::from_decscusr(ps) {
+                    Some((style, blinking)) => {
+                        self.cursor.style = style;
+                        self.cursor.blinking = blinking;
+                    }
+                    None => debug!("Unknown cursor style: {}", ps),
+                }
+            }
+            ([b'"'], 'q') => {
+                // DECSCA - Select Character Protection Attribute
+                match param(params, 0).unwrap_or(0) {
+                    0 | 2 => self.current_protected = false,
+                    1 => self.current_protected = true,
+                    n => debug!("Unknown protection attribute: {}", n),
+                }
+            }
+            ([b'*'], 'x') => {
+                // DECSACE - Select Attribute Change Extent
+                match param(params, 0).unwrap_or(0) {
+                    0 | 1 => self.rectangular_attribute_extent = false,
+                    2 => self.rectangular_attribute_extent = true,
+                    n => debug!("Unknown attribute change extent: {}", n),
+                }
+            }
+            // DECFRA - Fill Rectangular Area
+            ([b'$'], 'x') => self.fill_rect_area(params),
+            ([b'$'], 'z') => {
+                // DECERA - Erase Rectangular Area
+                let rect = self.rect_param(params, 0);
+                self.grid().erase_rect(rect);
+            }
+            ([b'$'], '{') => {
+                // DECSERA - Selective Erase Rectangular Area
+                let rect = self.rect_param(params, 0);
+                self.grid().selective_erase_rect(rect);
+            }
+            // DECCRA - Copy Rectangular Area
+            ([b'$'], 'v') => self.copy_rect_area(params),
+            // DECCARA - Change Attributes in Rectangular Area
+            ([b'$'], 'r') => self.change_rect_attributes(params),
+            ([b'$'], 'p') => {
+                // DECRQM - Request ANSI mode
+                let mode = param(params, 0).unwrap_or(0);
+                let state = mode_report_value(self.ansi_mode_state(mode));
+                self.respond(format!("\x1b[{};{}$y", mode, state).as_bytes());
             }
+            _ => {
+                debug!("Unhandled CSI: {:?} {:?} {}", intermediates, params, action);
+            }
+        }
+    }
+
+    /// Get the state of a DEC private mode, or None if it is not supported
+    fn private_mode_state(&self, mode: u16) -> Option<bool> {
+        let mouse = self.modes.mouse_mode();
+        match mode {
+            1 => Some(self.modes.is_cursor_keys_app()),
+            6 => Some(self.modes.is_origin_mode()),
+            7 => Some(self.modes.is_auto_wrap()),
+            12 => Some(self.cursor.blinking),
+            25 => Some(self.modes.is_cursor_visible()),
+            69 => Some(self.modes.is_left_right_margin()),
+            47 | 1047 | 1049 => Some(self.modes.is_alt_screen()),
+            1000 => Some(mouse == super::modes::MouseMode::X10),
+            1002 => Some(mouse == super::modes::MouseMode::ButtonEvent),
+            1003 => Some(mouse == super::modes::MouseMode::AnyEvent),
+            1004 => Some(self.modes.is_focus_report()),
+            1006 => Some(mouse == super::modes::MouseMode::Sgr),
+            2004 => Some(self.modes.is_bracketed_paste()),
+            2026 => Some(self.modes.is_synchronized_output()),
+            _ => None,
+        }
+    }
+
+    /// Get the state of an ANSI mode, or None if it is not supported
+    fn ansi_mode_state(&self, mode: u16) -> Option<bool> {
+        match mode {
+            4 => Some(self.modes.is_insert_mode()),
+            _ => None,
         }
     }
 
@@ -487,6 +2044,8 @@ impl TerminalState {
                             self.modes.remove(TerminalModes::ORIGIN_MODE);
                             debug!("Origin mode disabled");
                         }
+                        // Both set and reset home the cursor to the new origin
+                        self.goto(0, 0);
                     }
                     7 => {
                         // DECAWM - Auto-wrap Mode
@@ -498,6 +2057,10 @@ impl TerminalState {
                             debug!("Auto-wrap disabled");
                         }
                     }
+                    12 => {
+                        // att610 - Blinking cursor
+                        self.cursor.blinking = is_set;
+                    }
                     25 => {
                         // DECTCEM - Cursor Visibility
                         if is_set {
@@ -510,6 +2073,17 @@ impl TerminalState {
                             debug!("Cursor hidden");
                         }
                     }
+                    69 => {
+                        // DECLRMM - Left/Right Margin Mode
+                        if is_set {
+                            self.modes.insert(TerminalModes::LEFT_RIGHT_MARGIN);
+                            debug!("Left/right margin mode enabled");
+                        } else {
+                            self.modes.remove(TerminalModes::LEFT_RIGHT_MARGIN);
+                            self.reset_horizontal_margins();
+                            debug!("Left/right margin mode disabled");
+                        }
+                    }
                     47 | 1047 => {
                         // Alternate screen buffer (without clearing)
                         if is_set {
@@ -521,36 +2095,26 @@ impl TerminalState {
                     1048 => {
                         // Save/Restore cursor
                         if is_set {
-                            // Save cursor
-                            match self.active_grid {
-                                GridType::Primary => {
-                                    self.saved_cursor_primary = self.cursor.clone();
-                                }
-                                GridType::Alternate => {
-                                    self.saved_cursor_alternate = self.cursor.clone();
-                                }
-                            }
-                            debug!("Cursor saved");
+                            self.save_cursor();
                         } else {
-                            // Restore cursor
-                            self.cursor = match self.active_grid {
-                                GridType::Primary => self.saved_cursor_primary.clone(),
-                                GridType::Alternate => self.saved_cursor_alternate.clone(),
-                            };
-                            debug!("Cursor restored");
+                            self.restore_cursor();
                         }
                     }
                     1049 => {
                         // Alternate screen buffer with cursor save/restore
                         if is_set {
                             // Save cursor and switch to alternate screen
-                            self.saved_cursor_primary = self.cursor.clone();
+                            if self.active_grid == GridType::Primary {
+                                self.save_cursor();
+                            }
                             self.use_alternate_screen();
                             self.grid().clear();
                         } else {
                             // Switch to primary screen and restore cursor
-                            self.use_primary_screen();
-                            self.cursor = self.saved_cursor_primary.clone();
+                            if self.active_grid == GridType::Alternate {
+                                self.use_primary_screen();
+                                self.restore_cursor();
+                            }
                         }
                     }
                     1004 => {
@@ -610,6 +2174,17 @@ impl TerminalState {
                             debug!("Bracketed paste disabled");
                         }
                     }
+                    2026 => {
+                        // Synchronized output
+                        if is_set {
+                            self.modes.insert(TerminalModes::SYNCHRONIZED_OUTPUT);
+                            trace!("Synchronized update started");
+                        } else if self.modes.is_synchronized_output() {
+                            self.modes.remove(TerminalModes::SYNCHRONIZED_OUTPUT);
+                            self.frame_completed = true;
+                            trace!("Synchronized update finished");
+                        }
+                    }
                     _ => {
                         debug!("Unhandled DECSET/DECRST: {} ({})", n, if is_set { "set" } else { "reset" });
                     }
@@ -645,6 +2220,8 @@ impl TerminalState {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::theme::Rgb;
+    use crate::terminal::{NamedColor, Point, Selection};
 
     #[test]
     fn test_parser_creation() {
@@ -691,18 +2268,1580 @@ mod tests {
         assert_eq!(parser.grid().get(1, 0).unwrap().c, 'B');
     }
 
+    /// Collect the first character of each visible row
+    fn first_column(parser: &Parser) -> String {
+        (0..parser.grid().rows()).map(|row| parser.grid().get(0, row).unwrap().c).collect()
+    }
+
     #[test]
-    fn test_parser_ansi_clear() {
+    fn test_scroll_region_linefeed() {
+        let mut parser = Parser::new(10, 5);
+        parser.advance_bytes(b"A\r\nB\r\nC\r\nD\r\nE");
+        // Region covers rows 2..=4 (1-indexed), cursor is homed
+        parser.advance_bytes(b"\x1b[2;4r");
+        assert_eq!(parser.scroll_region(), (1, 3));
+        assert_eq!((parser.cursor().col, parser.cursor().row), (0, 0));
+
+        // Line feed at the bottom margin scrolls only the region
+        parser.advance_bytes(b"\x1b[4;1H\nX");
+        assert_eq!(first_column(&parser), "ACDXE");
+        assert_eq!(parser.grid().scrollback_len(), 0);
+    }
+
+    #[test]
+    fn test_scroll_region_full_screen_feeds_scrollback() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes(b"A\r\nB\r\nC\r\nD");
+        assert_eq!(first_column(&parser), "BCD");
+        assert_eq!(parser.grid().scrollback_len(), 1);
+    }
+
+    #[test]
+    fn test_linefeed_below_region_does_not_scroll() {
+        let mut parser = Parser::new(10, 5);
+        parser.advance_bytes(b"\x1b[1;2r\x1b[5;1HZ\n");
+        assert_eq!(parser.cursor().row, 4);
+        assert_eq!(parser.grid().get(0, 4).unwrap().c, 'Z');
+    }
+
+    #[test]
+    fn test_reverse_index_at_top_margin() {
+        let mut parser = Parser::new(10, 5);
+        parser.advance_bytes(b"A\r\nB\r\nC\r\nD\r\nE");
+        parser.advance_bytes(b"\x1b[2;4r\x1b[2;1H\x1bM");
+        assert_eq!(first_column(&parser), "A BCE");
+        assert_eq!(parser.cursor().row, 1);
+    }
+
+    #[test]
+    fn test_invalid_scroll_region_ignored() {
+        let mut parser = Parser::new(10, 5);
+        parser.advance_bytes(b"\x1b[4;2r");
+        assert_eq!(parser.scroll_region(), (0, 4));
+        parser.advance_bytes(b"\x1b[3;99r");
+        assert_eq!(parser.scroll_region(), (2, 4));
+        parser.advance_bytes(b"\x1b[r");
+        assert_eq!(parser.scroll_region(), (0, 4));
+    }
+
+    #[test]
+    fn test_scroll_up_down_within_region() {
+        let mut parser = Parser::new(10, 5);
+        parser.advance_bytes(b"A\r\nB\r\nC\r\nD\r\nE");
+        parser.advance_bytes(b"\x1b[2;4r\x1b[2S");
+        assert_eq!(first_column(&parser), "AD  E");
+        parser.advance_bytes(b"\x1b[T");
+        assert_eq!(first_column(&parser), "A D E");
+    }
+
+    #[test]
+    fn test_origin_mode_cursor_position() {
+        let mut parser = Parser::new(10, 10);
+        parser.advance_bytes(b"\x1b[3;6r\x1b[?6h\x1b[1;1H");
+        assert_eq!(parser.cursor().row, 2);
+
+        // Rows beyond the region are clamped to the bottom margin
+        parser.advance_bytes(b"\x1b[9;1H");
+        assert_eq!(parser.cursor().row, 5);
+
+        parser.advance_bytes(b"\x1b[?6l\x1b[9;1H");
+        assert_eq!(parser.cursor().row, 8);
+    }
+
+    #[test]
+    fn test_resize_resets_scroll_region() {
+        let mut parser = Parser::new(10, 10);
+        parser.advance_bytes(b"\x1b[3;6r\x1b[10;10H");
+        parser.resize(5, 4);
+        assert_eq!(parser.scroll_region(), (0, 3));
+        assert_eq!((parser.cursor().col, parser.cursor().row), (4, 3));
+    }
+
+    #[test]
+    fn test_esc_save_restore_cursor() {
         let mut parser = Parser::new(80, 24);
-        // Write some text
-        for byte in b"Hello" {
-            parser.advance(*byte);
-        }
-        // Clear screen: ESC[2J
-        for byte in b"\x1b[2J" {
-            parser.advance(*byte);
-        }
-        // Grid should be empty
+        parser.advance_bytes(b"\x1b[5;10H\x1b7\x1b[1;1H");
+        assert_eq!((parser.cursor().col, parser.cursor().row), (0, 0));
+        parser.advance_bytes(b"\x1b8");
+        assert_eq!((parser.cursor().col, parser.cursor().row), (9, 4));
+    }
+
+    #[test]
+    fn test_save_restore_cursor_pen_and_modes() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[1;31;44m\x1b(0\x1b[5;20r\x1b[?6h\x1b[2;3H\x1b7");
+        parser.advance_bytes(b"\x1b[0m\x1b(B\x1b[?6l\x1b[10;10H");
+
+        parser.advance_bytes(b"\x1b8q");
+        // Origin mode is restored, so the position is relative to the region
+        assert_eq!(cursor_pos(&parser), (3, 5));
+        let cell = parser.grid().get(2, 5).unwrap();
+        assert_eq!(cell.c, '─');
+        assert_eq!(cell.fg, Color::Named(NamedColor::Red));
+        assert_eq!(cell.bg, Color::Named(NamedColor::Blue));
+        assert!(cell.flags.contains(CellFlags::BOLD));
+        assert!(parser.modes().contains(TerminalModes::ORIGIN_MODE));
+    }
+
+    #[test]
+    fn test_csi_save_restore_cursor() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[4m\x1b[3;7H\x1b[s\x1b[0m\x1b[H\x1b[u");
+        assert_eq!(cursor_pos(&parser), (6, 2));
+        parser.advance_bytes(b"x");
+        assert!(parser.grid().get(6, 2).unwrap().flags.contains(CellFlags::UNDERLINE));
+    }
+
+    #[test]
+    fn test_restore_without_save_resets_pen() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[7m\x1b[5;5H\x1b8x");
+        assert_eq!(cursor_pos(&parser), (1, 0));
+        assert!(parser.grid().get(0, 0).unwrap().flags.is_empty());
+    }
+
+    #[test]
+    fn test_save_restore_keeps_pending_wrap() {
+        let mut parser = Parser::new(5, 3);
+        parser.advance_bytes(b"abcde\x1b7\x1b[H\x1b8f");
+        assert_eq!(row_text(&parser, 0), "abcde");
+        assert_eq!(row_text(&parser, 1), "f    ");
+    }
+
+    #[test]
+    fn test_saved_cursor_is_per_screen() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[32m\x1b[2;2H\x1b7");
+        parser.advance_bytes(b"\x1b[?47h\x1b[35m\x1b[9;9H\x1b7\x1b[0m\x1b[H");
+        parser.advance_bytes(b"\x1b8");
+        assert_eq!(cursor_pos(&parser), (8, 8));
+
+        parser.advance_bytes(b"\x1b[?47l\x1b8x");
+        assert_eq!(cursor_pos(&parser), (2, 1));
+        let cell = parser.grid().get(1, 1).unwrap();
+        assert_eq!(cell.fg, Color::Named(NamedColor::Green));
+    }
+
+    #[test]
+    fn test_alt_screen_1049_round_trip() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[1;33m\x1b(0\x1b[7;12H");
+        parser.advance_bytes(b"\x1b[?1049h");
+        assert!(parser.is_alt_screen());
+        // The cursor stays where it was on the alternate screen
+        assert_eq!(cursor_pos(&parser), (11, 6));
+
+        parser.advance_bytes(b"\x1b[0m\x1b(B\x1b[20;1Hvim");
+        parser.advance_bytes(b"\x1b[?1049l");
+        assert!(!parser.is_alt_screen());
+        assert_eq!(cursor_pos(&parser), (11, 6));
+
+        parser.advance_bytes(b"q");
+        let cell = parser.grid().get(11, 6).unwrap();
+        assert_eq!(cell.c, '─');
+        assert_eq!(cell.fg, Color::Named(NamedColor::Yellow));
+        assert!(cell.flags.contains(CellFlags::BOLD));
+    }
+
+    #[test]
+    fn test_esc_restore_cursor_clamps_after_resize() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[20;70H\x1b7");
+        parser.resize(40, 10);
+        parser.advance_bytes(b"\x1b8");
+        assert_eq!((parser.cursor().col, parser.cursor().row), (39, 9));
+    }
+
+    #[test]
+    fn test_esc_index() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes(b"A\x1bD");
+        assert_eq!((parser.cursor().col, parser.cursor().row), (1, 1));
+        // Index at the bottom scrolls the screen
+        parser.advance_bytes(b"\x1bD\x1bD");
+        assert_eq!(parser.cursor().row, 2);
+        assert_eq!(first_column(&parser), "   ");
+        assert_eq!(parser.grid().scrollback_len(), 1);
+    }
+
+    #[test]
+    fn test_esc_reverse_index() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes(b"\x1b[2;1H\x1bM");
+        assert_eq!(parser.cursor().row, 0);
+        // Reverse index at the top inserts a blank line
+        parser.advance_bytes(b"A\r\x1bM");
+        assert_eq!(first_column(&parser), " A ");
+    }
+
+    #[test]
+    fn test_esc_next_line() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes(b"ABC\x1bEX");
+        assert_eq!(parser.grid().get(0, 1).unwrap().c, 'X');
+        assert_eq!((parser.cursor().col, parser.cursor().row), (1, 1));
+    }
+
+    #[test]
+    fn test_esc_horizontal_tab_set() {
+        let mut parser = Parser::new(20, 3);
+        parser.advance_bytes(b"\x1b[1;4H\x1bH\r\tX");
+        assert_eq!(parser.grid().get(3, 0).unwrap().c, 'X');
+        // Default stops are still in place
+        parser.advance_bytes(b"\tY");
+        assert_eq!(parser.grid().get(8, 0).unwrap().c, 'Y');
+    }
+
+    #[test]
+    fn test_esc_full_reset() {
+        let mut parser = Parser::new(10, 5);
+        parser.advance_bytes(b"Hello\x1b[2;4r\x1b[?2004h\x1b(0\x1b[31m\x1b[3;3H");
+        parser.advance_bytes(b"\x1bc");
+
         assert!(parser.grid().get(0, 0).unwrap().is_empty());
+        assert_eq!((parser.cursor().col, parser.cursor().row), (0, 0));
+        assert_eq!(parser.scroll_region(), (0, 4));
+        assert_eq!(*parser.modes(), TerminalModes::new());
+
+        // Pen and character sets are back to defaults
+        parser.advance_bytes(b"q");
+        let cell = parser.grid().get(0, 0).unwrap();
+        assert_eq!(cell.c, 'q');
+        assert_eq!(cell.fg, Color::default());
+    }
+
+    #[test]
+    fn test_esc_full_reset_leaves_alternate_screen() {
+        let mut parser = Parser::new(10, 5);
+        parser.advance_bytes(b"\x1b[?1049h\x1bc");
+        assert!(!parser.is_alt_screen());
+    }
+
+    #[test]
+    fn test_esc_keypad_modes() {
+        let mut parser = Parser::new(10, 5);
+        parser.advance_bytes(b"\x1b=");
+        assert!(parser.modes().is_keypad_app());
+        parser.advance_bytes(b"\x1b>");
+        assert!(!parser.modes().is_keypad_app());
+    }
+
+    #[test]
+    fn test_esc_screen_alignment() {
+        let mut parser = Parser::new(4, 2);
+        parser.advance_bytes(b"\x1b[2;2H\x1b#8");
+        for row in 0..2 {
+            for col in 0..4 {
+                assert_eq!(parser.grid().get(col, row).unwrap().c, 'E');
+            }
+        }
+        assert_eq!((parser.cursor().col, parser.cursor().row), (0, 0));
+    }
+
+    #[test]
+    fn test_esc_designate_special_graphics() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes(b"\x1b(0lqk\x1b(Bq");
+        let row: String = (0..4).map(|col| parser.grid().get(col, 0).unwrap().c).collect();
+        assert_eq!(row, "┌─┐q");
+    }
+
+    #[test]
+    fn test_shift_out_and_shift_in() {
+        let mut parser = Parser::new(10, 3);
+        // Designate line drawing into G1, then switch with SO/SI
+        parser.advance_bytes(b"\x1b)0q\x0eqx\x0fq");
+        assert_eq!(row_text(&parser, 0), "q─│q      ");
+    }
+
+    #[test]
+    fn test_locking_shifts_g2_g3() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes(b"\x1b*A\x1b+0\x1bn#\x1boj\x0f#");
+        assert_eq!(row_text(&parser, 0), "£┘#       ");
+    }
+
+    #[test]
+    fn test_single_shifts() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes(b"\x1b*A\x1b+0\x1bN##\x1bOqq");
+        assert_eq!(row_text(&parser, 0), "£#─q      ");
+    }
+
+    #[test]
+    fn test_uk_charset() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes(b"\x1b(A#1\x1b(B#");
+        assert_eq!(row_text(&parser, 0), "£1#       ");
+    }
+
+    #[test]
+    fn test_decsc_saves_shift_state() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes(b"\x1b)0\x0e\x1b7\x0f\x1b)B\x1b8q");
+        assert_eq!(parser.grid().get(0, 0).unwrap().c, '─');
+
+        // RIS returns to G0
+        parser.advance_bytes(b"\x1bcq");
+        assert_eq!(parser.grid().get(0, 0).unwrap().c, 'q');
+    }
+
+    #[test]
+    fn test_esc_designate_g1_does_not_affect_g0() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes(b"\x1b)0q");
+        assert_eq!(parser.grid().get(0, 0).unwrap().c, 'q');
+    }
+
+    /// Collect a visible row as a string
+    fn row_text(parser: &Parser, row: usize) -> String {
+        (0..parser.grid().cols()).map(|col| parser.grid().get(col, row).unwrap().c).collect()
+    }
+
+    #[test]
+    fn test_insert_character() {
+        let mut parser = Parser::new(8, 2);
+        parser.advance_bytes(b"abcdefgh\x1b[1;3H\x1b[2@");
+        assert_eq!(row_text(&parser, 0), "ab  cdef");
+        assert_eq!(parser.cursor().col, 2);
+
+        // Default count is 1
+        parser.advance_bytes(b"\x1b[@");
+        assert_eq!(row_text(&parser, 0), "ab   cde");
+    }
+
+    #[test]
+    fn test_delete_character() {
+        let mut parser = Parser::new(8, 2);
+        parser.advance_bytes(b"abcdefgh\x1b[1;3H\x1b[2P");
+        assert_eq!(row_text(&parser, 0), "abefgh  ");
+
+        // Counts larger than the rest of the line clear to the end
+        parser.advance_bytes(b"\x1b[1;2H\x1b[99P");
+        assert_eq!(row_text(&parser, 0), "a       ");
+    }
+
+    #[test]
+    fn test_delete_character_in_last_column() {
+        let mut parser = Parser::new(4, 2);
+        parser.advance_bytes(b"abcd\x1b[P");
+        assert_eq!(row_text(&parser, 0), "abc ");
+    }
+
+    #[test]
+    fn test_erase_character() {
+        let mut parser = Parser::new(8, 2);
+        parser.advance_bytes(b"abcdefgh\x1b[1;3H\x1b[3X");
+        assert_eq!(row_text(&parser, 0), "ab   fgh");
+        assert_eq!(parser.cursor().col, 2);
+    }
+
+    #[test]
+    fn test_background_color_erase() {
+        let mut parser = Parser::new(4, 3);
+        parser.advance_bytes(b"\x1b[2;1Hab\x1b[1;4;44m\x1b[2;2H\x1b[K\x1b[3;1H\n");
+        let blue = Color::Named(NamedColor::Blue);
+
+        let grid = parser.grid();
+        // Erased cells keep the background only
+        let erased = grid.get(1, 0).unwrap();
+        assert_eq!(erased.bg, blue);
+        assert!(erased.flags.is_empty());
+        assert_eq!(grid.get(0, 0).unwrap().bg, Color::Named(NamedColor::Background));
+        // Lines scrolled in at the bottom are filled too
+        assert!((0..4).all(|col| grid.get(col, 2).unwrap().bg == blue));
+    }
+
+    #[test]
+    fn test_background_color_erase_follows_pen() {
+        let mut parser = Parser::new(4, 2);
+        parser.advance_bytes(b"\x1b[41m\x1b7\x1b[0m\x1b8\x1b[2J");
+        assert_eq!(parser.grid().get(0, 0).unwrap().bg, Color::Named(NamedColor::Red));
+
+        parser.advance_bytes(b"\x1b[m\x1b[2J");
+        assert_eq!(parser.grid().get(0, 0).unwrap().bg, Color::Named(NamedColor::Background));
+
+        // The alternate screen erases with the same pen
+        parser.advance_bytes(b"\x1b[42m\x1b[?1049h");
+        assert_eq!(parser.grid().get(0, 0).unwrap().bg, Color::Named(NamedColor::Green));
+    }
+
+    #[test]
+    fn test_insert_line() {
+        let mut parser = Parser::new(2, 5);
+        parser.advance_bytes(b"A\r\nB\r\nC\r\nD\r\nE\x1b[2;2H\x1b[2L");
+        assert_eq!(first_column(&parser), "A  BC");
+        assert_eq!(parser.cursor().col, 0);
+    }
+
+    #[test]
+    fn test_insert_line_within_scroll_region() {
+        let mut parser = Parser::new(2, 5);
+        parser.advance_bytes(b"A\r\nB\r\nC\r\nD\r\nE\x1b[2;4r\x1b[3;1H\x1b[L");
+        assert_eq!(first_column(&parser), "AB CE");
+
+        // Outside the region IL is ignored
+        parser.advance_bytes(b"\x1b[5;1H\x1b[L");
+        assert_eq!(first_column(&parser), "AB CE");
+    }
+
+    #[test]
+    fn test_delete_line() {
+        let mut parser = Parser::new(2, 5);
+        parser.advance_bytes(b"A\r\nB\r\nC\r\nD\r\nE\x1b[2;1H\x1b[2M");
+        assert_eq!(first_column(&parser), "ADE  ");
+        assert_eq!(parser.grid().scrollback_len(), 0);
+    }
+
+    #[test]
+    fn test_delete_line_within_scroll_region() {
+        let mut parser = Parser::new(2, 5);
+        parser.advance_bytes(b"A\r\nB\r\nC\r\nD\r\nE\x1b[2;4r\x1b[2;1H\x1b[M");
+        assert_eq!(first_column(&parser), "ACD E");
+    }
+
+    #[test]
+    fn test_repeat_character() {
+        let mut parser = Parser::new(8, 2);
+        parser.advance_bytes(b"x\x1b[3b");
+        assert_eq!(row_text(&parser, 0), "xxxx    ");
+        assert_eq!(parser.cursor().col, 4);
+
+        // Repeats wrap like ordinary printing
+        parser.advance_bytes(b"\x1b[5b");
+        assert_eq!(row_text(&parser, 0), "xxxxxxxx");
+        assert_eq!(row_text(&parser, 1), "x       ");
+    }
+
+    #[test]
+    fn test_repeat_without_previous_character() {
+        let mut parser = Parser::new(8, 2);
+        parser.advance_bytes(b"\x1b[3b");
+        assert_eq!(row_text(&parser, 0), "        ");
+    }
+
+    /// Get the cursor position as `(col, row)`
+    fn cursor_pos(parser: &Parser) -> (usize, usize) {
+        (parser.cursor().col, parser.cursor().row)
+    }
+
+    #[test]
+    fn test_cursor_relative_motion() {
+        let mut parser = Parser::new(20, 10);
+        parser.advance_bytes(b"\x1b[5;5H\x1b[2A");
+        assert_eq!(cursor_pos(&parser), (4, 2));
+        parser.advance_bytes(b"\x1b[3B");
+        assert_eq!(cursor_pos(&parser), (4, 5));
+        parser.advance_bytes(b"\x1b[4C");
+        assert_eq!(cursor_pos(&parser), (8, 5));
+        parser.advance_bytes(b"\x1b[6D");
+        assert_eq!(cursor_pos(&parser), (2, 5));
+
+        // A zero count moves by one
+        parser.advance_bytes(b"\x1b[0A");
+        assert_eq!(cursor_pos(&parser), (2, 4));
+
+        // Motion is clamped to the screen
+        parser.advance_bytes(b"\x1b[99A\x1b[99D");
+        assert_eq!(cursor_pos(&parser), (0, 0));
+        parser.advance_bytes(b"\x1b[99B\x1b[99C");
+        assert_eq!(cursor_pos(&parser), (19, 9));
+    }
+
+    #[test]
+    fn test_cursor_motion_stops_at_margins() {
+        let mut parser = Parser::new(20, 10);
+        parser.advance_bytes(b"\x1b[3;7r\x1b[5;1H\x1b[99A");
+        assert_eq!(parser.cursor().row, 2);
+        parser.advance_bytes(b"\x1b[99B");
+        assert_eq!(parser.cursor().row, 6);
+
+        // Outside the region the screen edges apply
+        parser.advance_bytes(b"\x1b[2;1H\x1b[99A");
+        assert_eq!(parser.cursor().row, 0);
+        parser.advance_bytes(b"\x1b[9;1H\x1b[99B");
+        assert_eq!(parser.cursor().row, 9);
+    }
+
+    #[test]
+    fn test_cursor_next_previous_line() {
+        let mut parser = Parser::new(20, 10);
+        parser.advance_bytes(b"\x1b[5;5H\x1b[2E");
+        assert_eq!(cursor_pos(&parser), (0, 6));
+        parser.advance_bytes(b"\x1b[1;9H\x1b[3F");
+        assert_eq!(cursor_pos(&parser), (0, 0));
+        parser.advance_bytes(b"\x1b[5;9H\x1b[F");
+        assert_eq!(cursor_pos(&parser), (0, 3));
+    }
+
+    #[test]
+    fn test_cursor_horizontal_absolute() {
+        let mut parser = Parser::new(20, 10);
+        parser.advance_bytes(b"\x1b[3;3H\x1b[10G");
+        assert_eq!(cursor_pos(&parser), (9, 2));
+        parser.advance_bytes(b"\x1b[G");
+        assert_eq!(cursor_pos(&parser), (0, 2));
+        parser.advance_bytes(b"\x1b[15`");
+        assert_eq!(cursor_pos(&parser), (14, 2));
+        parser.advance_bytes(b"\x1b[99G");
+        assert_eq!(cursor_pos(&parser), (19, 2));
+    }
+
+    #[test]
+    fn test_line_position_absolute() {
+        let mut parser = Parser::new(20, 10);
+        parser.advance_bytes(b"\x1b[3;6H\x1b[8d");
+        assert_eq!(cursor_pos(&parser), (5, 7));
+        parser.advance_bytes(b"\x1b[d");
+        assert_eq!(cursor_pos(&parser), (5, 0));
+        parser.advance_bytes(b"\x1b[99d");
+        assert_eq!(cursor_pos(&parser), (5, 9));
+    }
+
+    #[test]
+    fn test_line_position_absolute_origin_mode() {
+        let mut parser = Parser::new(20, 10);
+        parser.advance_bytes(b"\x1b[4;8r\x1b[?6h\x1b[2d");
+        assert_eq!(parser.cursor().row, 4);
+        parser.advance_bytes(b"\x1b[99d");
+        assert_eq!(parser.cursor().row, 7);
+    }
+
+    #[test]
+    fn test_character_and_line_position_relative() {
+        let mut parser = Parser::new(20, 10);
+        parser.advance_bytes(b"\x1b[2;2H\x1b[3a");
+        assert_eq!(cursor_pos(&parser), (4, 1));
+        parser.advance_bytes(b"\x1b[4e");
+        assert_eq!(cursor_pos(&parser), (4, 5));
+        parser.advance_bytes(b"\x1b[99a\x1b[99e");
+        assert_eq!(cursor_pos(&parser), (19, 9));
+    }
+
+    #[test]
+    fn test_horizontal_vertical_position() {
+        let mut parser = Parser::new(20, 10);
+        parser.advance_bytes(b"\x1b[4;6f");
+        assert_eq!(cursor_pos(&parser), (5, 3));
+        parser.advance_bytes(b"\x1b[;3f");
+        assert_eq!(cursor_pos(&parser), (2, 0));
+        parser.advance_bytes(b"\x1b[f");
+        assert_eq!(cursor_pos(&parser), (0, 0));
+    }
+
+    #[test]
+    fn test_tab_clear() {
+        let mut parser = Parser::new(30, 3);
+        parser.advance_bytes(b"\x1b[1;9H\x1b[g\r\t");
+        assert_eq!(parser.cursor().col, 16);
+
+        parser.advance_bytes(b"\x1b[3g\r\t");
+        assert_eq!(parser.cursor().col, 29);
+    }
+
+    #[test]
+    fn test_custom_tab_stops_after_clear_all() {
+        let mut parser = Parser::new(30, 3);
+        parser.advance_bytes(b"\x1b[3g\x1b[1;5H\x1bH\x1b[1;12H\x1bH\r");
+        parser.advance_bytes(b"\tA\tB");
+        assert_eq!(parser.grid().get(4, 0).unwrap().c, 'A');
+        assert_eq!(parser.grid().get(11, 0).unwrap().c, 'B');
+    }
+
+    #[test]
+    fn test_cursor_forward_tabulation() {
+        let mut parser = Parser::new(30, 3);
+        parser.advance_bytes(b"\x1b[2I");
+        assert_eq!(parser.cursor().col, 16);
+        parser.advance_bytes(b"\x1b[I");
+        assert_eq!(parser.cursor().col, 24);
+        parser.advance_bytes(b"\x1b[5I");
+        assert_eq!(parser.cursor().col, 29);
+    }
+
+    #[test]
+    fn test_cursor_backward_tabulation() {
+        let mut parser = Parser::new(30, 3);
+        parser.advance_bytes(b"\x1b[1;20H\x1b[Z");
+        assert_eq!(parser.cursor().col, 16);
+        parser.advance_bytes(b"\x1b[2Z");
+        assert_eq!(parser.cursor().col, 0);
+        parser.advance_bytes(b"\x1b[Z");
+        assert_eq!(parser.cursor().col, 0);
+    }
+
+    #[test]
+    fn test_tab_stops_reset_by_ris() {
+        let mut parser = Parser::new(30, 3);
+        parser.advance_bytes(b"\x1b[3g\x1bc\t");
+        assert_eq!(parser.cursor().col, 8);
+    }
+
+    #[test]
+    fn test_tab_stops_follow_resize() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes(b"\x1b[1;4H\x1bH");
+        parser.resize(40, 3);
+        parser.advance_bytes(b"\r\t");
+        assert_eq!(parser.cursor().col, 3);
+        parser.advance_bytes(b"\x1b[3I");
+        assert_eq!(parser.cursor().col, 24);
+    }
+
+    #[test]
+    fn test_primary_device_attributes() {
+        let mut parser = Parser::new(80, 24);
+        assert!(!parser.has_responses());
+        parser.advance_bytes(b"\x1b[c\x1b[0c");
+        assert!(parser.has_responses());
+        assert_eq!(parser.take_responses(), b"\x1b[?62;22c\x1b[?62;22c");
+        assert!(!parser.has_responses());
+    }
+
+    #[test]
+    fn test_secondary_device_attributes() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[>c");
+        assert_eq!(parser.take_responses(), b"\x1b[>1;10;0c");
+    }
+
+    #[test]
+    fn test_device_status_report() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[5n");
+        assert_eq!(parser.take_responses(), b"\x1b[0n");
+    }
+
+    #[test]
+    fn test_cursor_position_report() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[5;12H\x1b[6n");
+        assert_eq!(parser.take_responses(), b"\x1b[5;12R");
+
+        parser.advance_bytes(b"\x1b[?6n");
+        assert_eq!(parser.take_responses(), b"\x1b[?5;12;1R");
+    }
+
+    #[test]
+    fn test_cursor_position_report_origin_mode() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[5;20r\x1b[?6h\x1b[3;4H\x1b[6n");
+        assert_eq!(parser.take_responses(), b"\x1b[3;4R");
+    }
+
+    #[test]
+    fn test_cursor_position_report_after_last_column() {
+        let mut parser = Parser::new(4, 2);
+        parser.advance_bytes(b"abcd\x1b[6n");
+        assert_eq!(parser.take_responses(), b"\x1b[1;4R");
+    }
+
+    #[test]
+    fn test_private_mode_query_does_not_change_mode() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[?6n");
+        assert!(parser.modes().is_auto_wrap());
+        assert!(!parser.modes().is_origin_mode());
+    }
+
+    #[test]
+    fn test_request_private_mode() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[?2004$p\x1b[?7$p");
+        assert_eq!(parser.take_responses(), b"\x1b[?2004;2$y\x1b[?7;1$y");
+
+        parser.advance_bytes(b"\x1b[?2004h\x1b[?1006h\x1b[?2004$p\x1b[?1006$p\x1b[?1000$p");
+        assert_eq!(parser.take_responses(), b"\x1b[?2004;1$y\x1b[?1006;1$y\x1b[?1000;2$y");
+
+        // Unknown modes are reported as not recognized
+        parser.advance_bytes(b"\x1b[?9999$p");
+        assert_eq!(parser.take_responses(), b"\x1b[?9999;0$y");
+    }
+
+    #[test]
+    fn test_decscusr_sets_cursor_style() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[5 q");
+        assert_eq!(parser.cursor().style, CursorStyle::Bar);
+        assert!(parser.cursor().blinking);
+
+        parser.advance_bytes(b"\x1b[4 q");
+        assert_eq!(parser.cursor().style, CursorStyle::Underline);
+        assert!(!parser.cursor().blinking);
+
+        // Unknown styles are ignored, 0 restores the default
+        parser.advance_bytes(b"\x1b[9 q");
+        assert_eq!(parser.cursor().style, CursorStyle::Underline);
+        parser.advance_bytes(b"\x1b[ q");
+        assert_eq!(parser.cursor().style, CursorStyle::Block);
+        assert!(!parser.cursor().blinking);
+    }
+
+    #[test]
+    fn test_cursor_blink_mode() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[?12h\x1b[?12$p");
+        assert!(parser.cursor().blinking);
+        assert_eq!(parser.take_responses(), b"\x1b[?12;1$y");
+
+        parser.advance_bytes(b"\x1b[?12l");
+        assert!(!parser.cursor().blinking);
+    }
+
+    #[test]
+    fn test_decrqss_cursor_style() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1bP$q q\x1b\\");
+        assert_eq!(parser.take_responses(), b"\x1bP1$r2 q\x1b\\");
+
+        parser.advance_bytes(b"\x1b[3 q\x1bP$q q\x1b\\");
+        assert_eq!(parser.take_responses(), b"\x1bP1$r3 q\x1b\\");
+    }
+
+    #[test]
+    fn test_decrqss_sgr_and_margins() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1bP$qm\x1b\\");
+        assert_eq!(parser.take_responses(), b"\x1bP1$r0m\x1b\\");
+
+        parser.advance_bytes(b"\x1b[1;4:3;91;48;5;200;58;2;1;2;3m\x1bP$qm\x1b\\");
+        assert_eq!(parser.take_responses(), b"\x1bP1$r0;1;4:3;91;48:5:200;58:2::1:2:3m\x1b\\");
+
+        parser.advance_bytes(b"\x1b[5;20r\x1bP$qr\x1b\\");
+        assert_eq!(parser.take_responses(), b"\x1bP1$r5;20r\x1b\\");
+    }
+
+    #[test]
+    fn test_decrqss_invalid_request() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1bP$qx\x1b\\");
+        assert_eq!(parser.take_responses(), b"\x1bP0$r\x1b\\");
+
+        // Other DCS strings are not answered
+        parser.advance_bytes(b"\x1bP+q544e\x1b\\");
+        assert!(parser.take_responses().is_empty());
+    }
+
+    #[test]
+    fn test_request_ansi_mode() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[4$p\x1b[4h\x1b[4$p\x1b[20$p");
+        assert_eq!(parser.take_responses(), b"\x1b[4;2$y\x1b[4;1$y\x1b[20;0$y");
+    }
+
+    #[test]
+    fn test_responses_survive_reset() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[5n\x1bc");
+        assert_eq!(parser.take_responses(), b"\x1b[0n");
+    }
+
+    #[test]
+    fn test_osc_set_title_and_icon_name() {
+        let mut parser = Parser::new(80, 24);
+        assert_eq!(parser.title(), None);
+
+        parser.advance_bytes(b"\x1b]0;user@host: ~\x07");
+        assert_eq!(parser.title(), Some("user@host: ~"));
+        assert_eq!(parser.icon_name(), Some("user@host: ~"));
+
+        parser.advance_bytes(b"\x1b]2;vim main.rs\x1b\\");
+        assert_eq!(parser.title(), Some("vim main.rs"));
+        assert_eq!(parser.icon_name(), Some("user@host: ~"));
+
+        parser.advance_bytes(b"\x1b]1;vim\x07");
+        assert_eq!(parser.title(), Some("vim main.rs"));
+        assert_eq!(parser.icon_name(), Some("vim"));
+    }
+
+    #[test]
+    fn test_osc_title_keeps_semicolons_and_utf8() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes("\x1b]2;a;b — ünïcødé\x07".as_bytes());
+        assert_eq!(parser.title(), Some("a;b — ünïcødé"));
+    }
+
+    #[test]
+    fn test_title_stack() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b]0;shell\x07\x1b[22;0t\x1b]0;vim\x07");
+        assert_eq!(parser.title(), Some("vim"));
+
+        parser.advance_bytes(b"\x1b[23;0t");
+        assert_eq!(parser.title(), Some("shell"));
+        assert_eq!(parser.icon_name(), Some("shell"));
+
+        // Popping an empty stack is a no-op
+        parser.advance_bytes(b"\x1b[23;0t");
+        assert_eq!(parser.title(), Some("shell"));
+    }
+
+    #[test]
+    fn test_title_stack_title_only() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b]0;shell\x07\x1b[22;2t\x1b]0;vim\x07\x1b[23;2t");
+        assert_eq!(parser.title(), Some("shell"));
+        assert_eq!(parser.icon_name(), Some("vim"));
+    }
+
+    #[test]
+    fn test_title_stack_keeps_what_was_not_pushed() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b]0;shell\x07\x1b[22;2t\x1b]0;vim\x07\x1b[23;0t");
+        assert_eq!(parser.title(), Some("shell"));
+        assert_eq!(parser.icon_name(), Some("vim"));
+
+        parser.advance_bytes(b"\x1b]0;shell\x07\x1b[22;1t\x1b]0;vim\x07\x1b[23t");
+        assert_eq!(parser.title(), Some("vim"));
+        assert_eq!(parser.icon_name(), Some("shell"));
+    }
+
+    #[test]
+    fn test_title_stack_is_bounded() {
+        let mut parser = Parser::new(80, 24);
+        for i in 0..20 {
+            parser.advance_bytes(format!("\x1b]2;{}\x07\x1b[22t", i).as_bytes());
+        }
+        for _ in 0..20 {
+            parser.advance_bytes(b"\x1b[23t");
+        }
+        assert_eq!(parser.title(), Some("10"));
+    }
+
+    /// Get the URI of the hyperlink at a visible cell
+    fn link_uri(parser: &Parser, col: usize, row: usize) -> Option<String> {
+        parser.grid().hyperlink_at(Point::new(col, row)).map(|link| link.uri.clone())
+    }
+
+    #[test]
+    fn test_osc8_hyperlink() {
+        let mut parser = Parser::new(20, 3);
+        parser.advance_bytes(b"a\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x1b\\b");
+
+        assert_eq!(link_uri(&parser, 0, 0), None);
+        for col in 1..5 {
+            assert_eq!(link_uri(&parser, col, 0).as_deref(), Some("https://example.com"));
+        }
+        assert_eq!(link_uri(&parser, 5, 0), None);
+    }
+
+    #[test]
+    fn test_osc8_hyperlink_id_and_semicolons() {
+        let mut parser = Parser::new(20, 3);
+        parser.advance_bytes(b"\x1b]8;foo=bar:id=42;http://h/?a=1;b=2\x07x\x1b]8;;\x07");
+        let link = parser.grid().hyperlink_at(Point::new(0, 0)).unwrap();
+        assert_eq!(link.id.as_deref(), Some("42"));
+        assert_eq!(link.uri, "http://h/?a=1;b=2");
+    }
+
+    #[test]
+    fn test_osc8_cells_share_interned_link() {
+        let mut parser = Parser::new(20, 3);
+        parser.advance_bytes(b"\x1b]8;;file:///a\x07ab\x1b]8;;\x07 \x1b]8;;file:///a\x07c");
+        let grid = parser.grid();
+        let first = grid.get(0, 0).unwrap().extra;
+        assert!(first.is_some());
+        assert_eq!(grid.get(1, 0).unwrap().extra, first);
+        assert_eq!(grid.get(3, 0).unwrap().extra, first);
+    }
+
+    #[test]
+    fn test_osc8_hyperlink_preserved_in_scrollback() {
+        let mut parser = Parser::new(10, 2);
+        parser.advance_bytes(b"\x1b]8;;https://example.com\x07x\x1b]8;;\x07\r\n\n");
+        let grid = parser.grid();
+        assert_eq!(grid.scrollback_len(), 1);
+        let cell = grid.scrollback_line(0).unwrap()[0];
+        let link = grid.hyperlink(grid.cell_hyperlink(&cell).unwrap()).unwrap();
+        assert_eq!(link.uri, "https://example.com");
+    }
+
+    #[test]
+    fn test_osc8_link_ids_of_overwritten_cells_are_reused() {
+        let mut parser = Parser::new(10, 2);
+        parser.advance_bytes(b"\x1b[1;2H\x1b]8;;https://kept\x07k");
+        // More distinct links than there are ids, all on one cell
+        for i in 0..70_000 {
+            parser.advance_bytes(format!("\x1b[H\x1b]8;;https://{}\x07x", i).as_bytes());
+        }
+        assert_eq!(link_uri(&parser, 0, 0).as_deref(), Some("https://69999"));
+        assert_eq!(link_uri(&parser, 1, 0).as_deref(), Some("https://kept"));
+
+        parser.advance_bytes(b"\x1b]8;;http://new\x07\x1b[2;1Hy");
+        assert_eq!(link_uri(&parser, 0, 1).as_deref(), Some("http://new"));
+    }
+
+    #[test]
+    fn test_osc8_erase_removes_link() {
+        let mut parser = Parser::new(10, 2);
+        parser.advance_bytes(b"\x1b]8;;https://example.com\x07xy\x1b]8;;\x07\x1b[2K");
+        assert_eq!(link_uri(&parser, 0, 0), None);
+    }
+
+    #[test]
+    fn test_osc52_store() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b]52;c;aGVsbG8=\x07");
+        assert_eq!(
+            parser.take_clipboard_requests(),
+            vec![ClipboardRequest::Store { selection: "c".into(), text: "hello".into() }]
+        );
+        assert!(parser.take_clipboard_requests().is_empty());
+    }
+
+    #[test]
+    fn test_osc52_store_invalid_payload_ignored() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b]52;c;!!!\x07");
+        assert!(parser.take_clipboard_requests().is_empty());
+    }
+
+    #[test]
+    fn test_osc52_load_denied() {
+        let mut parser = Parser::new(80, 24);
+        parser.set_clipboard_read_policy(ClipboardReadPolicy::Deny);
+        parser.advance_bytes(b"\x1b]52;c;?\x07");
+        assert!(parser.take_clipboard_requests().is_empty());
+        // The application gets empty data instead of waiting for a reply
+        assert_eq!(parser.take_responses(), b"\x1b]52;c;\x07");
+
+        parser.advance_bytes(b"\x1b]52;;?\x1b\\");
+        assert_eq!(parser.take_responses(), b"\x1b]52;s0;\x1b\\");
+    }
+
+    #[test]
+    fn test_osc52_load_ask_requires_confirmation() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b]52;;?\x07");
+        assert_eq!(
+            parser.take_clipboard_requests(),
+            vec![ClipboardRequest::Load { selection: "s0".into(), confirm: true }]
+        );
+
+        // A read the user denies is answered with empty data
+        parser.respond_clipboard("s0", "");
+        assert_eq!(parser.take_responses(), b"\x1b]52;s0;\x1b\\");
+    }
+
+    #[test]
+    fn test_osc52_load_allowed_and_answered() {
+        let mut parser = Parser::new(80, 24);
+        parser.set_clipboard_read_policy(ClipboardReadPolicy::Allow);
+        parser.advance_bytes(b"\x1b]52;c;?\x1b\\");
+        assert_eq!(
+            parser.take_clipboard_requests(),
+            vec![ClipboardRequest::Load { selection: "c".into(), confirm: false }]
+        );
+
+        parser.respond_clipboard("c", "hello");
+        assert_eq!(parser.take_responses(), b"\x1b]52;c;aGVsbG8=\x1b\\");
+    }
+
+    #[test]
+    fn test_osc52_policy_survives_reset() {
+        let mut parser = Parser::new(80, 24);
+        parser.set_clipboard_read_policy(ClipboardReadPolicy::Deny);
+        parser.advance_bytes(b"\x1bc\x1b]52;c;?\x07");
+        assert!(parser.take_clipboard_requests().is_empty());
+    }
+
+    #[test]
+    fn test_osc4_set_and_query() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b]4;1;rgb:12/34/56;200;#abcdef\x07");
+        assert_eq!(parser.palette().indexed(1), Rgb::new(0x12, 0x34, 0x56));
+        assert_eq!(parser.palette().indexed(200), Rgb::new(0xab, 0xcd, 0xef));
+
+        parser.advance_bytes(b"\x1b]4;1;?\x07");
+        assert_eq!(parser.take_responses(), b"\x1b]4;1;rgb:1212/3434/5656\x07");
+
+        // ST-terminated queries are answered with ST
+        parser.advance_bytes(b"\x1b]4;200;?\x1b\\");
+        assert_eq!(parser.take_responses(), b"\x1b]4;200;rgb:abab/cdcd/efef\x1b\\");
+    }
+
+    #[test]
+    fn test_osc4_invalid_ignored() {
+        let mut parser = Parser::new(80, 24);
+        let before = parser.palette().clone();
+        parser.advance_bytes(b"\x1b]4;256;#ffffff\x07\x1b]4;3;nonsense\x07");
+        assert_eq!(parser.palette(), &before);
+    }
+
+    #[test]
+    fn test_osc104_reset() {
+        let mut parser = Parser::new(80, 24);
+        let theme = Theme::nord();
+        parser.set_theme(&theme);
+        parser.advance_bytes(b"\x1b]4;1;#000001;2;#000002;3;#000003\x07");
+
+        parser.advance_bytes(b"\x1b]104;1\x07");
+        assert_eq!(parser.palette().indexed(1), theme.ansi[1]);
+        assert_eq!(parser.palette().indexed(2), Rgb::new(0, 0, 2));
+
+        parser.advance_bytes(b"\x1b]104\x07");
+        assert_eq!(parser.palette().indexed(2), theme.ansi[2]);
+        assert_eq!(parser.palette().indexed(3), theme.ansi[3]);
+    }
+
+    #[test]
+    fn test_osc11_query_background() {
+        let mut parser = Parser::new(80, 24);
+        parser.set_theme(&Theme::catppuccin_mocha());
+        parser.advance_bytes(b"\x1b]11;?\x1b\\");
+        assert_eq!(parser.take_responses(), b"\x1b]11;rgb:1e1e/1e1e/2e2e\x1b\\");
+    }
+
+    #[test]
+    fn test_osc10_sets_consecutive_colors() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b]10;#010203;#040506;#070809\x07");
+        let palette = parser.palette();
+        assert_eq!(palette.special(SpecialColor::Foreground), Rgb::new(1, 2, 3));
+        assert_eq!(palette.special(SpecialColor::Background), Rgb::new(4, 5, 6));
+        assert_eq!(palette.special(SpecialColor::Cursor), Rgb::new(7, 8, 9));
+
+        parser.advance_bytes(b"\x1b]10;?;?\x07");
+        assert_eq!(
+            parser.take_responses(),
+            b"\x1b]10;rgb:0101/0202/0303\x07\x1b]11;rgb:0404/0505/0606\x07"
+        );
+    }
+
+    #[test]
+    fn test_osc_special_color_reset() {
+        let mut parser = Parser::new(80, 24);
+        let theme = Theme::dracula();
+        parser.set_theme(&theme);
+        parser.advance_bytes(b"\x1b]11;#000000\x07\x1b]17;#111111\x07\x1b]12;#222222\x07");
+
+        parser.advance_bytes(b"\x1b]111\x07\x1b]117\x07");
+        assert_eq!(parser.palette().special(SpecialColor::Background), theme.background);
+        assert_eq!(
+            parser.palette().special(SpecialColor::SelectionBackground),
+            theme.selection_background
+        );
+        assert_eq!(parser.palette().special(SpecialColor::Cursor), Rgb::new(0x22, 0x22, 0x22));
+
+        parser.advance_bytes(b"\x1b]112\x07");
+        assert_eq!(parser.palette().special(SpecialColor::Cursor), theme.cursor.unwrap());
+    }
+
+    #[test]
+    fn test_palette_reset_by_ris_keeps_theme() {
+        let mut parser = Parser::new(80, 24);
+        let theme = Theme::tokyo_night();
+        parser.set_theme(&theme);
+        parser.advance_bytes(b"\x1b]4;1;#000000\x07\x1b]10;#000000\x07");
+        parser.advance_bytes(b"\x1bc");
+        assert_eq!(parser.palette(), &Palette::from_theme(&theme));
+    }
+
+    #[test]
+    fn test_osc133_records_commands() {
+        let mut parser = Parser::new(20, 5);
+        parser.advance_bytes(b"\x1b]133;A\x07$ \x1b]133;B\x07false\r\n\x1b]133;C\x07");
+        parser.advance_bytes(b"oops\r\n\x1b]133;D;1\x07");
+        parser.advance_bytes(b"\x1b]133;A\x07$ \x1b]133;B\x07sleep 9\r\n\x1b]133;C\x07");
+
+        let commands = parser.commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].prompt, Point::new(0, 0));
+        assert_eq!(commands[0].input, Some(Point::new(2, 0)));
+        assert!(commands[0].failed());
+        assert_eq!(parser.command_output(&commands[0]), "oops");
+        assert!(!commands[1].is_finished());
+        assert_eq!(commands[1].exit_code, None);
+    }
+
+    #[test]
+    fn test_osc133_marks_survive_scrollback() {
+        let mut parser = Parser::new(20, 3);
+        parser.advance_bytes(b"\x1b]133;A\x07$ \x1b]133;B\x07seq 3\r\n\x1b]133;C\x07");
+        parser.advance_bytes(b"1\r\n2\r\n3\r\n\x1b]133;D;0\x07\x1b]133;A\x07$ ");
+        assert_eq!(parser.grid().scrollback_len(), 2);
+
+        let commands = parser.commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].exit_code, Some(0));
+        assert_eq!(parser.command_output(&commands[0]), "1\n2\n3");
+        assert_eq!(commands[1].prompt, Point::new(0, 4));
+    }
+
+    #[test]
+    fn test_osc7_working_directory() {
+        let mut parser = Parser::new(80, 24);
+        assert_eq!(parser.working_directory(), None);
+
+        parser.advance_bytes(b"\x1b]7;file:///tmp/dir%20one\x07");
+        assert_eq!(parser.working_directory(), Some(Path::new("/tmp/dir one")));
+
+        // Paths may contain semicolons
+        parser.advance_bytes(b"\x1b]7;file://localhost/tmp/a;b\x1b\\");
+        assert_eq!(parser.working_directory(), Some(Path::new("/tmp/a;b")));
+
+        // Malformed URIs keep the last directory
+        parser.advance_bytes(b"\x1b]7;/etc\x07");
+        assert_eq!(parser.working_directory(), Some(Path::new("/tmp/a;b")));
+
+        parser.advance_bytes(b"\x1bc");
+        assert_eq!(parser.working_directory(), Some(Path::new("/tmp/a;b")));
+
+        // A remote directory makes the last local one stale
+        parser.advance_bytes(b"\x1b]7;file://some.remote.host.invalid/etc\x07");
+        assert_eq!(parser.working_directory(), None);
+    }
+
+    #[test]
+    fn test_osc_notifications() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b]9;Build finished\x07");
+        parser.advance_bytes(b"\x1b]777;notify;cargo;tests passed\x1b\\");
+        parser.advance_bytes(b"\x1b]99;i=1:d=0;Deploy\x1b\\\x1b]99;i=1:p=body;done\x1b\\");
+        parser.advance_bytes(b"\x1b]9;4;1;50\x07");
+
+        assert_eq!(
+            parser.take_notifications(),
+            vec![
+                Notification::new(None, "Build finished"),
+                Notification::new(Some("cargo"), "tests passed"),
+                Notification::new(Some("Deploy"), "done"),
+            ]
+        );
+        assert!(parser.take_notifications().is_empty());
+    }
+
+    #[test]
+    fn test_pending_wrap_keeps_cursor_on_screen() {
+        let mut parser = Parser::new(5, 3);
+        parser.advance_bytes(b"abcde");
+        assert_eq!(cursor_pos(&parser), (4, 0));
+
+        // CR cancels the pending wrap
+        parser.advance_bytes(b"\rX");
+        assert_eq!(row_text(&parser, 0), "Xbcde");
+        assert_eq!(cursor_pos(&parser), (1, 0));
+        assert!(!parser.grid().is_wrapped(0));
+    }
+
+    #[test]
+    fn test_pending_wrap_cleared_by_cursor_motion() {
+        let mut parser = Parser::new(5, 3);
+        parser.advance_bytes(b"abcde\x08X");
+        assert_eq!(row_text(&parser, 0), "abcXe");
+
+        parser.advance_bytes(b"\x1b[1;5Hyz");
+        assert_eq!(row_text(&parser, 0), "abcXy");
+        assert_eq!(row_text(&parser, 1), "z    ");
+
+        parser.advance_bytes(b"\x1b[3;1Hvwxyz\x1b[KQ");
+        // EL erases the last column and cancels the wrap
+        assert_eq!(row_text(&parser, 2), "vwxyQ");
+        assert_eq!(cursor_pos(&parser), (4, 2));
+    }
+
+    #[test]
+    fn test_wrap_marks_row_soft_wrapped() {
+        let mut parser = Parser::new(5, 3);
+        parser.advance_bytes(b"abcdefg\r\nhi");
+        assert!(parser.grid().is_wrapped(0));
+        assert!(!parser.grid().is_wrapped(1));
+        assert_eq!(row_text(&parser, 1), "fg   ");
+
+        let text = Selection::line(0, 2).get_text(parser.grid());
+        assert_eq!(text, "abcdefg   \nhi");
+    }
+
+    #[test]
+    fn test_no_wrap_without_autowrap() {
+        let mut parser = Parser::new(5, 3);
+        parser.advance_bytes(b"\x1b[?7labcdefg");
+        assert_eq!(row_text(&parser, 0), "abcdg");
+        assert_eq!(cursor_pos(&parser), (4, 0));
+        assert!(!parser.grid().is_wrapped(0));
+    }
+
+    #[test]
+    fn test_wide_char_takes_two_cells() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes("a中b".as_bytes());
+        let grid = parser.grid();
+        assert_eq!(grid.get(1, 0).unwrap().c, '中');
+        assert!(grid.get(1, 0).unwrap().flags.contains(CellFlags::WIDE_CHAR));
+        assert!(grid.get(2, 0).unwrap().flags.contains(CellFlags::WIDE_CHAR_SPACER));
+        assert_eq!(grid.get(3, 0).unwrap().c, 'b');
+        assert_eq!(cursor_pos(&parser), (4, 0));
+        assert_eq!(Selection::line(0, 0).get_text(parser.grid()), "a中b");
+    }
+
+    #[test]
+    fn test_wide_char_wraps_early_at_right_edge() {
+        let mut parser = Parser::new(5, 3);
+        parser.advance_bytes("abcd中".as_bytes());
+        let grid = parser.grid();
+        assert!(grid.get(4, 0).unwrap().flags.contains(CellFlags::WIDE_CHAR_SPACER));
+        assert!(grid.is_wrapped(0));
+        assert_eq!(grid.get(0, 1).unwrap().c, '中');
+        assert_eq!(cursor_pos(&parser), (2, 1));
+        assert_eq!(Selection::line(0, 1).get_text(parser.grid()), "abcd中");
+    }
+
+    #[test]
+    fn test_wide_char_in_last_two_columns_defers_wrap() {
+        let mut parser = Parser::new(4, 3);
+        parser.advance_bytes("ab中".as_bytes());
+        assert_eq!(cursor_pos(&parser), (3, 0));
+        parser.advance_bytes(b"c");
+        assert_eq!(row_text(&parser, 1), "c   ");
+        assert!(parser.grid().is_wrapped(0));
+    }
+
+    #[test]
+    fn test_wide_char_without_autowrap_stays_on_row() {
+        let mut parser = Parser::new(5, 3);
+        parser.advance_bytes("\x1b[?7labcde中".as_bytes());
+        assert_eq!(parser.grid().get(3, 0).unwrap().c, '中');
+        assert_eq!(cursor_pos(&parser), (4, 0));
+        assert_eq!(parser.grid().get(0, 1).unwrap().c, ' ');
+    }
+
+    #[test]
+    fn test_overwriting_half_of_wide_char() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes("中文\x1b[1;2Hx".as_bytes());
+        let grid = parser.grid();
+        // Overwriting the spacer erases the wide character itself
+        assert_eq!(grid.get(0, 0).unwrap().c, ' ');
+        assert!(grid.get(0, 0).unwrap().flags.is_empty());
+        assert_eq!(grid.get(1, 0).unwrap().c, 'x');
+        assert_eq!(grid.get(2, 0).unwrap().c, '文');
+
+        // Overwriting the wide character erases its spacer
+        parser.advance_bytes(b"y");
+        let grid = parser.grid();
+        assert_eq!(grid.get(2, 0).unwrap().c, 'y');
+        assert!(grid.get(3, 0).unwrap().flags.is_empty());
+        assert_eq!(Selection::line(0, 0).get_text(parser.grid()), " xy");
+    }
+
+    #[test]
+    fn test_combining_mark_joins_previous_cell() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes("e\u{301}x".as_bytes());
+        let grid = parser.grid();
+        assert_eq!(grid.get(0, 0).unwrap().c, 'e');
+        assert_eq!(grid.zerowidth(grid.get(0, 0).unwrap()), "\u{301}");
+        assert_eq!(grid.get(1, 0).unwrap().c, 'x');
+        assert_eq!(cursor_pos(&parser), (2, 0));
+        assert_eq!(Selection::line(0, 0).get_text(parser.grid()), "e\u{301}x");
+    }
+
+    #[test]
+    fn test_zero_width_joiner_sequence_stays_in_sync() {
+        // Without grapheme clustering every emoji keeps its own wcwidth, so
+        // the cursor agrees with applications measuring with wcwidth()
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes("\u{1F468}\u{200D}\u{1F469}".as_bytes());
+        let grid = parser.grid();
+        assert_eq!(grid.zerowidth(grid.get(0, 0).unwrap()), "\u{200D}");
+        assert_eq!(grid.get(2, 0).unwrap().c, '\u{1F469}');
+        assert_eq!(cursor_pos(&parser), (4, 0));
+        assert_eq!(Selection::line(0, 0).get_text(parser.grid()), "\u{1F468}\u{200D}\u{1F469}");
+    }
+
+    #[test]
+    fn test_variation_selector_after_wide_char_in_last_column() {
+        let mut parser = Parser::new(4, 3);
+        parser.advance_bytes("abc\u{2764}\u{FE0F}".as_bytes());
+        // U+2764 is narrow; the selector joins it while the wrap is pending
+        assert_eq!(cursor_pos(&parser), (3, 0));
+        parser.advance_bytes("cd中\u{FE0F}".as_bytes());
+        let grid = parser.grid();
+        assert_eq!(grid.zerowidth(grid.get(3, 0).unwrap()), "\u{FE0F}");
+        assert_eq!(grid.zerowidth(grid.get(2, 1).unwrap()), "\u{FE0F}");
+        assert!(!grid.is_wrapped(1));
+    }
+
+    #[test]
+    fn test_combining_mark_at_line_start_is_dropped() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes("\u{301}a".as_bytes());
+        let grid = parser.grid();
+        assert_eq!(grid.get(0, 0).unwrap().c, 'a');
+        assert_eq!(grid.zerowidth(grid.get(0, 0).unwrap()), "");
+    }
+
+    #[test]
+    fn test_extra_ids_of_overwritten_cells_are_reused() {
+        let mut parser = Parser::new(10, 2);
+        parser.advance_bytes(b"\x1b[1;2H\x1b[58:2::1:2:3mk");
+        // More distinct underline colors than there are ids, all on one cell
+        for i in 0..70_000u32 {
+            let (r, g, b) = (i >> 16, (i >> 8) & 0xff, i & 0xff);
+            parser.advance_bytes(format!("\x1b[H\x1b[58:2::{}:{}:{}mx", r, g, b).as_bytes());
+        }
+        assert_eq!(underline(&parser, 0).1, Some(Color::Rgb(1, 0x11, 0x6f)));
+        assert_eq!(underline(&parser, 1).1, Some(Color::Rgb(1, 2, 3)));
+
+        parser.advance_bytes("\x1b[0m\x1b[1;2H\u{301}".as_bytes());
+        let grid = parser.grid();
+        assert_eq!(grid.zerowidth(grid.get(0, 0).unwrap()), "\u{301}");
+    }
+
+    /// Get the underline style and color of a visible cell
+    fn underline(parser: &Parser, col: usize) -> (Option<UnderlineStyle>, Option<Color>) {
+        let grid = parser.grid();
+        let cell = grid.get(col, 0).unwrap();
+        let color = grid.cell_extra(cell).and_then(|extra| extra.underline_color);
+        (cell.flags.underline_style(), color)
+    }
+
+    #[test]
+    fn test_sgr_underline_color() {
+        let mut parser = Parser::new(10, 2);
+        parser.advance_bytes(b"\x1b[4:3;58:2::255:0:16ma");
+        parser.advance_bytes(b"\x1b[58:2:1:2:3mb\x1b[58;2;4;5;6mc");
+        parser.advance_bytes(b"\x1b[58:5:9md\x1b[58;5;200;1me\x1b[59mf");
+        let curly_red = (Some(UnderlineStyle::Curly), Some(Color::Rgb(255, 0, 16)));
+        assert_eq!(underline(&parser, 0), curly_red);
+        assert_eq!(underline(&parser, 1).1, Some(Color::Rgb(1, 2, 3)));
+        assert_eq!(underline(&parser, 2).1, Some(Color::Rgb(4, 5, 6)));
+        assert_eq!(underline(&parser, 3).1, Some(Color::Named(NamedColor::BrightRed)));
+        // Parameters after the color are still applied
+        assert_eq!(underline(&parser, 4).1, Some(Color::Indexed(200)));
+        assert!(parser.grid().get(4, 0).unwrap().flags.contains(CellFlags::BOLD));
+        assert_eq!(underline(&parser, 5).1, None);
+
+        // SGR 0 resets the underline color too
+        parser.advance_bytes(b"\x1b[58;5;1m\x1b[0mg");
+        assert_eq!(underline(&parser, 6), (None, None));
+    }
+
+    #[test]
+    fn test_set_left_right_margins() {
+        let mut parser = Parser::new(80, 24);
+        // Without DECLRMM, CSI s saves the cursor
+        parser.advance_bytes(b"\x1b[3;4H\x1b[2;5s\x1b[H\x1b[u");
+        assert_eq!(parser.horizontal_margins(), (0, 79));
+        assert_eq!(cursor_pos(&parser), (3, 2));
+
+        parser.advance_bytes(b"\x1b[?69h\x1b[2;5s");
+        assert_eq!(parser.horizontal_margins(), (1, 4));
+        assert_eq!(cursor_pos(&parser), (0, 0));
+
+        // Invalid margins are ignored
+        parser.advance_bytes(b"\x1b[5;5s");
+        assert_eq!(parser.horizontal_margins(), (1, 4));
+
+        parser.advance_bytes(b"\x1b[?69$p\x1bP$qs\x1b\\");
+        assert_eq!(parser.take_responses(), b"\x1b[?69;1$y\x1bP1$r2;5s\x1b\\");
+
+        // Leaving the mode resets the margins
+        parser.advance_bytes(b"\x1b[?69l");
+        assert_eq!(parser.horizontal_margins(), (0, 79));
+    }
+
+    #[test]
+    fn test_margins_constrain_printing() {
+        let mut parser = Parser::new(10, 3);
+        parser.advance_bytes(b"\x1b[?69h\x1b[3;6s\x1b[1;3Habcdefg");
+        assert_eq!(row_text(&parser, 0), "  abcd    ");
+        assert_eq!(row_text(&parser, 1), "  efg     ");
+        // Wraps inside the margins are not soft line wraps
+        assert!(!parser.grid().is_wrapped(0));
+
+        // CR and BS stop at the left margin, CUF at the right margin
+        parser.advance_bytes(b"\r\x08\x1b[20C");
+        assert_eq!(cursor_pos(&parser), (5, 1));
+    }
+
+    #[test]
+    fn test_margins_constrain_insert_delete_characters() {
+        let mut parser = Parser::new(10, 2);
+        parser.advance_bytes(b"0123456789\x1b[?69h\x1b[3;6s\x1b[1;4H\x1b[@");
+        assert_eq!(row_text(&parser, 0), "012 346789");
+        parser.advance_bytes(b"\x1b[2P");
+        assert_eq!(row_text(&parser, 0), "0124  6789");
+
+        // Outside the margins nothing happens
+        parser.advance_bytes(b"\x1b[1;8H\x1b[P");
+        assert_eq!(row_text(&parser, 0), "0124  6789");
+    }
+
+    #[test]
+    fn test_margins_constrain_scrolling() {
+        let mut parser = Parser::new(6, 3);
+        parser.advance_bytes(b"ABCDEF\r\nGHIJKL\r\nMNOPQR");
+        parser.advance_bytes(b"\x1b[?69h\x1b[2;4s\x1b[3;2H\n");
+        assert_eq!(row_text(&parser, 0), "AHIJEF");
+        assert_eq!(row_text(&parser, 1), "GNOPKL");
+        assert_eq!(row_text(&parser, 2), "M   QR");
+        assert_eq!(parser.grid().scrollback_len(), 0);
+
+        parser.advance_bytes(b"\x1b[1;3H\x1b[L");
+        assert_eq!(row_text(&parser, 0), "A   EF");
+        assert_eq!(row_text(&parser, 1), "GHIJKL");
+        assert_eq!(row_text(&parser, 2), "MNOPQR");
+        // IL moves the cursor to the left margin
+        assert_eq!(cursor_pos(&parser), (1, 0));
+    }
+
+    #[test]
+    fn test_margins_scroll_whole_region() {
+        let fill = b"ABCDEF\r\nGHIJKL\r\nMNOPQR\x1b[?69h\x1b[2;4s";
+        let blanked = ["A   EF", "G   KL", "M   QR"];
+        // SU, SD, DL and IL by at least the height of the region blank it
+        for sequence in [&b"\x1b[10S"[..], b"\x1b[3T", b"\x1b[1;2H\x1b[99M", b"\x1b[1;2H\x1b[3L"] {
+            let mut parser = Parser::new(6, 3);
+            parser.advance_bytes(fill);
+            parser.advance_bytes(sequence);
+            for (row, expected) in blanked.iter().enumerate() {
+                assert_eq!(row_text(&parser, row), *expected, "{:?}", sequence);
+            }
+        }
+    }
+
+    #[test]
+    fn test_origin_mode_with_margins() {
+        let mut parser = Parser::new(10, 6);
+        parser.advance_bytes(b"\x1b[?69h\x1b[3;6s\x1b[2;4r\x1b[?6h");
+        assert_eq!(cursor_pos(&parser), (2, 1));
+        parser.advance_bytes(b"\x1b[2;3H\x1b[6n");
+        assert_eq!(parser.take_responses(), b"\x1b[2;3R");
+        parser.advance_bytes(b"\x1b[9;9H");
+        assert_eq!(cursor_pos(&parser), (5, 3));
+    }
+
+    #[test]
+    fn test_fill_and_erase_rectangular_area() {
+        let mut parser = Parser::new(6, 3);
+        parser.advance_bytes(b"\x1b[1;4m\x1b[88;1;2;2;4$x\x1b[0m");
+        assert_eq!(row_text(&parser, 0), " XXX  ");
+        assert_eq!(row_text(&parser, 1), " XXX  ");
+        assert_eq!(row_text(&parser, 2), "      ");
+        let flags = parser.grid().get(1, 0).unwrap().flags;
+        assert_eq!(flags, CellFlags::BOLD | CellFlags::UNDERLINE);
+        // The cursor does not move
+        assert_eq!(cursor_pos(&parser), (0, 0));
+
+        parser.advance_bytes(b"\x1b[1;3;2;3$z");
+        assert_eq!(row_text(&parser, 0), " X X  ");
+        assert_eq!(row_text(&parser, 1), " X X  ");
+
+        // Control characters cannot be used as the fill character
+        parser.advance_bytes(b"\x1b[10$x");
+        assert_eq!(row_text(&parser, 2), "      ");
+    }
+
+    #[test]
+    fn test_copy_rectangular_area() {
+        let mut parser = Parser::new(6, 3);
+        parser.advance_bytes(b"abcdef\x1b[1;1;1;3;1;3;4;1$v");
+        assert_eq!(row_text(&parser, 2), "   abc");
+
+        // Overlapping copies read the source before writing
+        parser.advance_bytes(b"\x1b[1;1;1;4;1;1;2;1$v");
+        assert_eq!(row_text(&parser, 0), "aabcdf");
+    }
+
+    #[test]
+    fn test_selective_erase_rectangular_area() {
+        let mut parser = Parser::new(6, 2);
+        parser.advance_bytes(b"\x1b[1\"qab\x1b[0\"qcd\x1b[2\"qef");
+        parser.advance_bytes(b"\x1bP$q\"q\x1b\\");
+        assert_eq!(parser.take_responses(), b"\x1bP1$r0\"q\x1b\\");
+
+        parser.advance_bytes(b"\x1b[${");
+        assert_eq!(row_text(&parser, 0), "ab    ");
+
+        // Ordinary erases ignore the protection
+        parser.advance_bytes(b"\x1b[2K");
+        assert_eq!(row_text(&parser, 0), "      ");
+    }
+
+    #[test]
+    fn test_change_attributes_in_rectangular_area() {
+        let mut parser = Parser::new(6, 3);
+        // The default extent is a stream from the first to the last position
+        parser.advance_bytes(b"\x1b[1;2;2;3;1;7$r");
+        let bold_inverse = CellFlags::BOLD | CellFlags::INVERSE;
+        let flags = |parser: &Parser, col, row| parser.grid().get(col, row).unwrap().flags;
+        assert!(flags(&parser, 0, 0).is_empty());
+        assert_eq!(flags(&parser, 5, 0), bold_inverse);
+        assert_eq!(flags(&parser, 2, 1), bold_inverse);
+        assert!(flags(&parser, 3, 1).is_empty());
+
+        // DECSACE 2 selects a rectangle
+        parser.advance_bytes(b"\x1b[2*x\x1b[2;2;3;3;0;4$r");
+        assert_eq!(flags(&parser, 0, 1), bold_inverse);
+        assert_eq!(flags(&parser, 1, 1), CellFlags::UNDERLINE);
+        assert_eq!(flags(&parser, 2, 2), CellFlags::UNDERLINE);
+        assert!(flags(&parser, 3, 2).is_empty());
+    }
+
+    #[test]
+    fn test_synchronized_output() {
+        let mut parser = Parser::new(80, 24);
+        assert!(parser.is_frame_ready());
+
+        parser.advance_bytes(b"\x1b[?2026h\x1b[?2026$p");
+        assert!(!parser.is_frame_ready());
+        assert_eq!(parser.take_responses(), b"\x1b[?2026;1$y");
+
+        parser.advance_bytes(b"frame\x1b[?2026l\x1b[?2026$p");
+        assert!(parser.is_frame_ready());
+        assert_eq!(parser.take_responses(), b"\x1b[?2026;2$y");
+
+        // RIS ends an unfinished update
+        parser.advance_bytes(b"\x1b[?2026h\x1bc");
+        assert!(parser.is_frame_ready());
+
+        // So does the timeout of the UI
+        parser.advance_bytes(b"\x1b[?2026h");
+        parser.end_synchronized_update();
+        assert!(parser.is_frame_ready());
+        parser.advance_bytes(b"\x1b[?2026$p");
+        assert_eq!(parser.take_responses(), b"\x1b[?2026;2$y");
+    }
+
+    #[test]
+    fn test_synchronized_output_back_to_back() {
+        let mut parser = Parser::new(80, 24);
+        parser.advance_bytes(b"\x1b[?2026h");
+        assert!(!parser.take_frame_ready());
+
+        // A finished update is presented even if the next one already started
+        parser.advance_bytes(b"one\x1b[?2026l\x1b[?2026htwo");
+        assert!(!parser.is_frame_ready());
+        assert!(parser.take_frame_ready());
+        assert!(!parser.take_frame_ready());
+
+        parser.advance_bytes(b"\x1b[?2026l");
+        assert!(parser.take_frame_ready());
+        assert!(parser.take_frame_ready());
+    }
+
+    #[test]
+    fn test_parser_ansi_clear() {
+        let mut parser = Parser::new(80, 24);
+        // Write some text
+        for byte in b"Hello" {
+            parser.advance(*byte);
+        }
+        // Clear screen: ESC[2J
+        for byte in b"\x1b[2J" {
+            parser.advance(*byte);
+        }
+        // Grid should be empty
+        assert!(parser.grid().get(0, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_erase_display_above() {
+        let mut parser = Parser::new(5, 3);
+        parser.advance_bytes(b"aaaaabbbbbccccc\x1b[2;3H\x1b[1J");
+        assert_eq!(row_text(&parser, 0), "     ");
+        assert_eq!(row_text(&parser, 1), "   bb");
+        assert_eq!(row_text(&parser, 2), "ccccc");
     }
 }
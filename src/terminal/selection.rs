@@ -127,10 +127,10 @@ impl Selection {
                 for row in start_row..=end_row.min(grid.rows() - 1) {
                     for col in 0..grid.cols() {
                         if let Some(cell) = grid.get(col, row) {
-                            text.push(cell.c);
+                            grid.push_cell_text(&mut text, cell);
                         }
                     }
-                    if row < end_row {
+                    if row < end_row && !grid.is_wrapped(row) {
                         text.push('\n');
                     }
                 }
@@ -151,7 +151,7 @@ impl Selection {
                 for row in min_row..=max_row.min(grid.rows() - 1) {
                     for col in min_col..=max_col.min(grid.cols() - 1) {
                         if let Some(cell) = grid.get(col, row) {
-                            text.push(cell.c);
+                            grid.push_cell_text(&mut text, cell);
                         }
                     }
                     if row < max_row {
@@ -204,7 +204,7 @@ fn get_text_in_range(grid: &Grid, start: Point, end: Point) -> String {
         // Single line selection
         for col in start.col..=end.col.min(grid.cols() - 1) {
             if let Some(cell) = grid.get(col, start.row) {
-                text.push(cell.c);
+                grid.push_cell_text(&mut text, cell);
             }
         }
     } else {
@@ -212,26 +212,30 @@ fn get_text_in_range(grid: &Grid, start: Point, end: Point) -> String {
         // First line (from start.col to end of line)
         for col in start.col..grid.cols() {
             if let Some(cell) = grid.get(col, start.row) {
-                text.push(cell.c);
+                grid.push_cell_text(&mut text, cell);
             }
         }
-        text.push('\n');
+        if !grid.is_wrapped(start.row) {
+            text.push('\n');
+        }
 
         // Middle lines (full lines)
         for row in (start.row + 1)..end.row {
             for col in 0..grid.cols() {
                 if let Some(cell) = grid.get(col, row) {
-                    text.push(cell.c);
+                    grid.push_cell_text(&mut text, cell);
                 }
             }
-            text.push('\n');
+            if !grid.is_wrapped(row) {
+                text.push('\n');
+            }
         }
 
         // Last line (from start to end.col)
         if end.row < grid.rows() {
             for col in 0..=end.col.min(grid.cols() - 1) {
                 if let Some(cell) = grid.get(col, end.row) {
-                    text.push(cell.c);
+                    grid.push_cell_text(&mut text, cell);
                 }
             }
         }
@@ -286,7 +290,7 @@ fn is_word_char(c: char) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::terminal::Cell;
+    use crate::terminal::{Cell, CellFlags};
 
     #[test]
     fn test_point_ordering() {
@@ -349,6 +353,50 @@ mod tests {
         assert_eq!(text, "Hello");
     }
 
+    #[test]
+    fn test_selection_get_text_joins_wrapped_rows() {
+        let mut grid = Grid::new(5, 3, 100);
+        for (i, c) in "Hello World".chars().enumerate() {
+            grid.set(i % 5, i / 5, Cell::new(c));
+        }
+        grid.get_mut(4, 0).unwrap().flags.insert(CellFlags::WRAPLINE);
+        grid.get_mut(4, 1).unwrap().flags.insert(CellFlags::WRAPLINE);
+
+        let sel = Selection::character(Point::new(0, 0), Point::new(4, 2));
+        assert_eq!(sel.get_text(&grid), "Hello World");
+        assert_eq!(Selection::line(0, 2).get_text(&grid), "Hello World");
+
+        grid.get_mut(4, 0).unwrap().flags.remove(CellFlags::WRAPLINE);
+        assert_eq!(sel.get_text(&grid), "Hello\n World");
+    }
+
+    #[test]
+    fn test_selection_get_text_skips_wide_char_spacers() {
+        let mut grid = Grid::new(10, 2, 100);
+        grid.set(0, 0, Cell { flags: CellFlags::WIDE_CHAR, ..Cell::new('中') });
+        grid.set(1, 0, Cell { flags: CellFlags::WIDE_CHAR_SPACER, ..Cell::default() });
+        grid.set(2, 0, Cell::new('!'));
+
+        let sel = Selection::character(Point::new(0, 0), Point::new(2, 0));
+        assert_eq!(sel.get_text(&grid), "中!");
+        let sel = Selection::block(Point::new(0, 0), Point::new(2, 0));
+        assert_eq!(sel.get_text(&grid), "中!");
+    }
+
+    #[test]
+    fn test_selection_get_text_includes_combining_characters() {
+        let mut grid = Grid::new(10, 2, 100);
+        grid.set(0, 0, Cell::new('e'));
+        grid.push_zerowidth(0, 0, '\u{301}');
+        grid.set(1, 0, Cell::new('x'));
+
+        let sel = Selection::character(Point::new(0, 0), Point::new(1, 0));
+        assert_eq!(sel.get_text(&grid), "e\u{301}x");
+        assert_eq!(Selection::line(0, 0).get_text(&grid), "e\u{301}x");
+        let sel = Selection::block(Point::new(0, 0), Point::new(0, 0));
+        assert_eq!(sel.get_text(&grid), "e\u{301}");
+    }
+
     #[test]
     fn test_selection_clear() {
         let mut sel = Selection::character(Point::new(0, 0), Point::new(10, 10));
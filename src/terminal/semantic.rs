@@ -0,0 +1,181 @@
+//! Shell integration (OSC 133 semantic prompts)
+//!
+//! Shells that support the FinalTerm protocol mark the start of the prompt
+//! (`A`), the start of the command line (`B`), the start of the command
+//! output (`C`) and the end of the command with its exit status (`D`).
+//! The marks are stored on grid lines so they scroll into the scrollback
+//! with the text they belong to, and are assembled into [`CommandRecord`]s
+//! on demand.
+
+use super::selection::Point;
+
+/// A semantic mark placed by the shell
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SemanticMark {
+    /// Start of the prompt (OSC 133 ; A)
+    PromptStart,
+    /// Start of the command line, i.e. end of the prompt (OSC 133 ; B)
+    CommandStart,
+    /// Start of the command output (OSC 133 ; C)
+    OutputStart,
+    /// End of the command with its exit status, if reported (OSC 133 ; D)
+    CommandEnd(Option<i32>),
+}
+
+impl SemanticMark {
+    /// Parse the parameters of an OSC 133 sequence
+    pub fn from_osc(params: &[&[u8]]) -> Option<Self> {
+        match *params.first()? {
+            b"A" => Some(Self::PromptStart),
+            b"B" => Some(Self::CommandStart),
+            b"C" => Some(Self::OutputStart),
+            b"D" => {
+                let exit_code = params
+                    .get(1)
+                    .and_then(|code| std::str::from_utf8(code).ok())
+                    .and_then(|code| code.parse().ok());
+                Some(Self::CommandEnd(exit_code))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A mark and the column it was placed at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineMark {
+    /// Column of the cursor when the mark was received
+    pub col: usize,
+    /// The mark
+    pub mark: SemanticMark,
+}
+
+/// A prompt and the command run from it
+///
+/// Points use absolute line numbers, where row 0 is the oldest line in the
+/// scrollback; they stay valid until the grid scrolls again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommandRecord {
+    /// Start of the prompt
+    pub prompt: Point,
+    /// Start of the command line
+    pub input: Option<Point>,
+    /// Start of the command output
+    pub output: Option<Point>,
+    /// End of the command output, `None` while the command is running
+    pub end: Option<Point>,
+    /// Exit status reported by the shell
+    pub exit_code: Option<i32>,
+}
+
+impl CommandRecord {
+    fn new(prompt: Point) -> Self {
+        Self { prompt, input: None, output: None, end: None, exit_code: None }
+    }
+
+    /// Check if the command has finished
+    pub fn is_finished(&self) -> bool {
+        self.end.is_some()
+    }
+
+    /// Check if the command finished with a non-zero exit status
+    pub fn failed(&self) -> bool {
+        self.exit_code.is_some_and(|code| code != 0)
+    }
+}
+
+/// Assemble command records from marks in line order
+///
+/// `lines` yields the absolute line number and marks of every line that
+/// has any. A new prompt ends a command that never reported `D`.
+pub fn collect_commands<'a>(
+    lines: impl IntoIterator<Item = (usize, &'a [LineMark])>,
+) -> Vec<CommandRecord> {
+    let mut commands: Vec<CommandRecord> = Vec::new();
+
+    for (row, marks) in lines {
+        for mark in marks {
+            let point = Point::new(mark.col, row);
+            let open = commands.last_mut().filter(|command| !command.is_finished());
+            match (mark.mark, open) {
+                (SemanticMark::PromptStart, Some(command)) => {
+                    if command.output.is_some() {
+                        command.end = Some(point);
+                    }
+                    let command = CommandRecord::new(point);
+                    if commands.last().is_some_and(|last| last.output.is_none()) {
+                        // A prompt that never ran a command is replaced
+                        commands.pop();
+                    }
+                    commands.push(command);
+                }
+                (SemanticMark::PromptStart, None) => commands.push(CommandRecord::new(point)),
+                (SemanticMark::CommandStart, Some(command)) => command.input = Some(point),
+                (SemanticMark::OutputStart, Some(command)) => command.output = Some(point),
+                (SemanticMark::CommandEnd(exit_code), Some(command)) => {
+                    command.output.get_or_insert(point);
+                    command.end = Some(point);
+                    command.exit_code = exit_code;
+                }
+                // Marks outside of a prompt are ignored
+                (_, None) => {}
+            }
+        }
+    }
+
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mark(col: usize, mark: SemanticMark) -> LineMark {
+        LineMark { col, mark }
+    }
+
+    #[test]
+    fn test_semantic_mark_from_osc() {
+        assert_eq!(SemanticMark::from_osc(&[b"A"]), Some(SemanticMark::PromptStart));
+        assert_eq!(SemanticMark::from_osc(&[b"A", b"aid=1"]), Some(SemanticMark::PromptStart));
+        assert_eq!(SemanticMark::from_osc(&[b"D", b"2"]), Some(SemanticMark::CommandEnd(Some(2))));
+        assert_eq!(SemanticMark::from_osc(&[b"D"]), Some(SemanticMark::CommandEnd(None)));
+        assert_eq!(SemanticMark::from_osc(&[b"Z"]), None);
+        assert_eq!(SemanticMark::from_osc(&[]), None);
+    }
+
+    #[test]
+    fn test_collect_commands() {
+        let line0 = [mark(0, SemanticMark::PromptStart), mark(2, SemanticMark::CommandStart)];
+        let line1 = [mark(0, SemanticMark::OutputStart)];
+        let line3 = [
+            mark(0, SemanticMark::CommandEnd(Some(1))),
+            mark(0, SemanticMark::PromptStart),
+            mark(2, SemanticMark::CommandStart),
+        ];
+        let commands = collect_commands([(0, &line0[..]), (1, &line1[..]), (3, &line3[..])]);
+
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].prompt, Point::new(0, 0));
+        assert_eq!(commands[0].input, Some(Point::new(2, 0)));
+        assert_eq!(commands[0].output, Some(Point::new(0, 1)));
+        assert_eq!(commands[0].end, Some(Point::new(0, 3)));
+        assert!(commands[0].failed());
+        assert!(!commands[1].is_finished());
+    }
+
+    #[test]
+    fn test_collect_commands_without_end_mark() {
+        let line0 = [mark(0, SemanticMark::PromptStart), mark(2, SemanticMark::OutputStart)];
+        let line1 = [mark(0, SemanticMark::PromptStart)];
+        let line2 = [mark(0, SemanticMark::PromptStart)];
+        let commands = collect_commands([(0, &line0[..]), (1, &line1[..]), (2, &line2[..])]);
+
+        // The first command ends at the next prompt; the empty prompt on
+        // line 1 is replaced by the one on line 2
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].end, Some(Point::new(0, 1)));
+        assert_eq!(commands[0].exit_code, None);
+        assert_eq!(commands[1].prompt, Point::new(0, 2));
+    }
+}
@@ -0,0 +1,141 @@
+//! Horizontal tab stop tracking
+//!
+//! Tab stops default to every 8 columns and can be changed by applications
+//! with HTS (`ESC H`) and TBC (`CSI g`). They are consulted by HT, CHT
+//! (`CSI I`) and CBT (`CSI Z`).
+
+/// Default distance between tab stops
+const TAB_WIDTH: usize = 8;
+
+/// Set of horizontal tab stops for one terminal
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TabStops {
+    /// Whether a tab stop is set at each column
+    stops: Vec<bool>,
+}
+
+impl TabStops {
+    /// Create tab stops every 8 columns for a screen `cols` wide
+    pub fn new(cols: usize) -> Self {
+        Self { stops: (0..cols).map(is_default_stop).collect() }
+    }
+
+    /// Resize to a screen `cols` wide
+    ///
+    /// Existing stops are kept; columns added when the screen grows get the
+    /// default stops every 8 columns.
+    pub fn resize(&mut self, cols: usize) {
+        let old_cols = self.stops.len();
+        self.stops.truncate(cols);
+        self.stops.extend((old_cols..cols).map(is_default_stop));
+    }
+
+    /// Set a tab stop at the given column
+    pub fn set(&mut self, col: usize) {
+        if let Some(stop) = self.stops.get_mut(col) {
+            *stop = true;
+        }
+    }
+
+    /// Clear the tab stop at the given column
+    pub fn clear(&mut self, col: usize) {
+        if let Some(stop) = self.stops.get_mut(col) {
+            *stop = false;
+        }
+    }
+
+    /// Clear all tab stops
+    pub fn clear_all(&mut self) {
+        self.stops.fill(false);
+    }
+
+    /// Check if a tab stop is set at the given column
+    pub fn is_set(&self, col: usize) -> bool {
+        self.stops.get(col).copied().unwrap_or(false)
+    }
+
+    /// Get the column of the next tab stop after `col`
+    ///
+    /// Returns the last column if there are no more tab stops.
+    pub fn next(&self, col: usize) -> usize {
+        let last = self.stops.len().saturating_sub(1);
+        (col + 1..self.stops.len()).find(|&c| self.stops[c]).unwrap_or(last)
+    }
+
+    /// Get the column of the previous tab stop before `col`
+    ///
+    /// Returns column 0 if there are no earlier tab stops.
+    pub fn prev(&self, col: usize) -> usize {
+        let col = col.min(self.stops.len());
+        (0..col).rev().find(|&c| self.stops[c]).unwrap_or(0)
+    }
+}
+
+/// Check if a column has a tab stop by default
+fn is_default_stop(col: usize) -> bool {
+    col > 0 && col % TAB_WIDTH == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_tab_stops() {
+        let tabs = TabStops::new(20);
+        assert!(!tabs.is_set(0));
+        assert!(tabs.is_set(8));
+        assert!(tabs.is_set(16));
+        assert_eq!(tabs.next(0), 8);
+        assert_eq!(tabs.next(8), 16);
+        assert_eq!(tabs.next(16), 19);
+    }
+
+    #[test]
+    fn test_custom_tab_stop() {
+        let mut tabs = TabStops::new(20);
+        tabs.set(3);
+        assert_eq!(tabs.next(0), 3);
+        assert_eq!(tabs.next(3), 8);
+        // Out of range columns are ignored
+        tabs.set(100);
+        assert!(!tabs.is_set(100));
+    }
+
+    #[test]
+    fn test_clear_tab_stops() {
+        let mut tabs = TabStops::new(20);
+        tabs.clear(8);
+        assert_eq!(tabs.next(0), 16);
+
+        tabs.clear_all();
+        assert_eq!(tabs.next(0), 19);
+        assert_eq!(tabs.prev(19), 0);
+    }
+
+    #[test]
+    fn test_previous_tab_stop() {
+        let tabs = TabStops::new(20);
+        assert_eq!(tabs.prev(19), 16);
+        assert_eq!(tabs.prev(16), 8);
+        assert_eq!(tabs.prev(5), 0);
+        assert_eq!(tabs.prev(100), 16);
+    }
+
+    #[test]
+    fn test_resize_keeps_custom_stops() {
+        let mut tabs = TabStops::new(10);
+        tabs.set(3);
+        tabs.clear(8);
+        tabs.resize(30);
+        assert!(tabs.is_set(3));
+        assert!(!tabs.is_set(8));
+        // New columns get default stops
+        assert!(tabs.is_set(16));
+        assert!(tabs.is_set(24));
+
+        tabs.resize(5);
+        assert!(tabs.is_set(3));
+        assert_eq!(tabs.next(3), 4);
+    }
+}
@@ -1,16 +1,31 @@
 //! Iced application shell for TermiEmu
 
 use crate::{
+    clipboard::{ClipboardManager, ClipboardRequest},
+    config::{Config, NotificationConfig, Theme},
+    notification::{LoggingNotifier, Notifier},
     pty::{Pty, PtyConfig},
     terminal::Parser,
+    ui::terminal_widget::TerminalCanvas,
 };
 use iced::{
     keyboard,
-    widget::{column, container, text},
-    Element, Event, Length, Subscription, Task,
+    widget::{canvas, column, container, text},
+    window, Element, Event, Length, Subscription, Task,
 };
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use tracing::{debug, info};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Time between phases of a blinking cursor
+const CURSOR_BLINK_INTERVAL: Duration = Duration::from_millis(530);
+
+/// Longest time a synchronized update (mode 2026) may hold back the screen
+///
+/// Protects against applications that never finish an update, for example
+/// because they crashed in the middle of one.
+const SYNCHRONIZED_OUTPUT_TIMEOUT: Duration = Duration::from_millis(150);
 
 /// Main application state
 pub struct TermiEmuApp {
@@ -21,6 +36,20 @@ pub struct TermiEmuApp {
     pty: Option<Arc<Mutex<Pty>>>,
     /// Error message if PTY fails
     error: Option<String>,
+    /// System clipboard
+    clipboard: ClipboardManager,
+    /// OSC 52 clipboard read waiting for the user's confirmation (selection)
+    pending_clipboard_read: Option<String>,
+    /// Shows notifications requested by applications
+    notifier: Box<dyn Notifier>,
+    /// Notification settings
+    notification_config: NotificationConfig,
+    /// Whether the window has keyboard focus
+    focused: bool,
+    /// Whether a blinking cursor is in its visible phase
+    cursor_blink_on: bool,
+    /// Geometry of the last presented terminal frame
+    terminal_cache: canvas::Cache,
 }
 
 /// Application messages
@@ -30,11 +59,17 @@ pub enum Message {
     TerminalOutput(Vec<u8>),
     /// Keyboard event
     KeyPressed(keyboard::Event),
+    /// Window gained (true) or lost (false) focus
+    WindowFocused(bool),
+    /// Toggle the phase of a blinking cursor
+    CursorBlink,
+    /// A synchronized update took too long to finish
+    SynchronizedOutputTimeout,
 }
 
 impl Default for TermiEmuApp {
     fn default() -> Self {
-        Self { parser: Parser::new(80, 24), pty: None, error: None }
+        Self::with_config(&Config::default(), None, None)
     }
 }
 
@@ -44,36 +79,182 @@ impl TermiEmuApp {
     pub fn new() -> (Self, Task<Message>) {
         info!("Initializing TermiEmu application");
 
+        let config = Config::load().unwrap_or_else(|e| {
+            warn!("Failed to load configuration, using defaults: {}", e);
+            Config::default()
+        });
+
         // Try to spawn PTY immediately
-        let (pty, error) = match spawn_pty() {
+        let (pty, error) = match spawn_pty(None) {
             Ok(p) => (Some(Arc::new(Mutex::new(p))), None),
             Err(e) => (None, Some(format!("Failed to spawn PTY: {}", e))),
         };
 
-        let app = Self { parser: Parser::new(80, 24), pty, error };
-
+        let mut app = Self::with_config(&config, pty, error);
+        app.set_notifier(desktop_notifier());
         (app, Task::none())
     }
 
+    /// Build the application state from a configuration
+    fn with_config(config: &Config, pty: Option<Arc<Mutex<Pty>>>, error: Option<String>) -> Self {
+        let mut parser = Parser::new(80, 24);
+        parser.set_clipboard_read_policy(config.terminal.clipboard_read);
+        parser.set_theme(&Theme::builtin(&config.theme).unwrap_or_else(|| {
+            warn!("Unknown theme '{}', using the default", config.theme);
+            Theme::catppuccin_mocha()
+        }));
+
+        Self {
+            parser,
+            pty,
+            error,
+            clipboard: ClipboardManager::new(),
+            pending_clipboard_read: None,
+            notifier: Box::new(LoggingNotifier::new()),
+            notification_config: config.notifications.clone(),
+            focused: true,
+            cursor_blink_on: true,
+            terminal_cache: canvas::Cache::new(),
+        }
+    }
+
+    /// Replace the notifier used for application notifications
+    pub fn set_notifier(&mut self, notifier: Box<dyn Notifier>) {
+        self.notifier = notifier;
+    }
+
+    /// Get the shell's current working directory
+    ///
+    /// Uses the directory reported via OSC 7, falling back to the directory
+    /// of the PTY's foreground process. New sessions are spawned here.
+    pub fn working_directory(&self) -> Option<PathBuf> {
+        if let Some(dir) = self.parser.working_directory() {
+            return Some(dir.to_path_buf());
+        }
+        let pty = self.pty.as_ref()?.lock().ok()?;
+        pty.foreground_working_directory()
+    }
+
+    /// Spawn a new PTY in the shell's current working directory
+    pub fn spawn_pty_in_working_directory(&self) -> anyhow::Result<Pty> {
+        spawn_pty(self.working_directory())
+    }
+
     /// Handle keyboard input
     fn handle_keyboard(&mut self, event: keyboard::Event) {
         if let keyboard::Event::KeyPressed { key, modifiers, .. } = event {
-            if let Some(pty_ref) = &self.pty {
-                if let Ok(mut pty) = pty_ref.lock() {
-                    if let Some(bytes) = key_to_bytes(key, modifiers) {
-                        debug!("Sending to PTY: {:?}", bytes);
-                        if let Err(e) = pty.write(&bytes) {
-                            self.error = Some(format!("PTY write error: {}", e));
-                        }
+            // A pending clipboard read is answered by the next key press
+            if let Some(selection) = self.pending_clipboard_read.take() {
+                match clipboard_prompt_answer(&key, modifiers) {
+                    ClipboardPromptAnswer::Allow => {
+                        self.answer_clipboard_read(&selection);
+                        return;
+                    }
+                    ClipboardPromptAnswer::Deny => {
+                        info!("Clipboard read denied by user");
+                        self.reply_clipboard(&selection, "");
                     }
+                    ClipboardPromptAnswer::Undecided => {
+                        self.pending_clipboard_read = Some(selection);
+                        return;
+                    }
+                }
+            }
+
+            if let Some(bytes) = key_to_bytes(key, modifiers) {
+                self.write_to_pty(&bytes);
+            }
+        }
+    }
+
+    /// Handle OSC 52 clipboard requests from the terminal
+    fn handle_clipboard_requests(&mut self) {
+        for request in self.parser.take_clipboard_requests() {
+            match request {
+                ClipboardRequest::Store { text, .. } => {
+                    if let Err(e) = self.clipboard.copy(&text) {
+                        warn!("OSC 52 clipboard write failed: {}", e);
+                    }
+                }
+                ClipboardRequest::Load { selection, confirm: true } => {
+                    info!("Application requested clipboard contents, waiting for confirmation");
+                    // Only the latest read waits, an earlier one is denied
+                    if let Some(replaced) = self.pending_clipboard_read.replace(selection) {
+                        self.reply_clipboard(&replaced, "");
+                    }
+                }
+                ClipboardRequest::Load { selection, confirm: false } => {
+                    self.answer_clipboard_read(&selection);
+                }
+            }
+        }
+    }
+
+    /// Show notifications requested by applications (OSC 9/99/777)
+    fn handle_notifications(&mut self) {
+        let notifications = self.parser.take_notifications();
+        let config = &self.notification_config;
+        if !config.enabled || (config.only_when_unfocused && self.focused) {
+            if !notifications.is_empty() {
+                debug!("Suppressed {} notification(s)", notifications.len());
+            }
+            return;
+        }
+        for notification in &notifications {
+            self.notifier.notify(notification);
+        }
+    }
+
+    /// Send the clipboard contents to the application (OSC 52 reply)
+    fn answer_clipboard_read(&mut self, selection: &str) {
+        let text = self.clipboard.paste().unwrap_or_else(|e| {
+            warn!("OSC 52 clipboard read failed: {}", e);
+            String::new()
+        });
+        self.reply_clipboard(selection, &text);
+    }
+
+    /// Send an OSC 52 reply to the application
+    ///
+    /// Like xterm, a read that is denied or fails gets an empty reply, so
+    /// that the application does not wait for one forever.
+    fn reply_clipboard(&mut self, selection: &str, text: &str) {
+        self.parser.respond_clipboard(selection, text);
+        let responses = self.parser.take_responses();
+        self.write_to_pty(&responses);
+    }
+
+    /// Write bytes to the PTY, recording any error for the status line
+    fn write_to_pty(&mut self, bytes: &[u8]) {
+        if let Some(pty_ref) = &self.pty {
+            if let Ok(mut pty) = pty_ref.lock() {
+                debug!("Sending to PTY: {:?}", bytes);
+                if let Err(e) = pty.write(bytes) {
+                    self.error = Some(format!("PTY write error: {}", e));
                 }
             }
         }
     }
 
     /// Get the window title
+    ///
+    /// Follows the title set by the shell (OSC 0/2), falling back to the
+    /// application name.
     pub fn title(&self) -> String {
-        String::from("TermiEmu - Modern Terminal Emulator")
+        match self.parser.title() {
+            Some(title) if !title.is_empty() => title.to_string(),
+            _ => String::from("TermiEmu - Modern Terminal Emulator"),
+        }
+    }
+
+    /// Draw the terminal again on the next frame
+    ///
+    /// While an application is in the middle of a synchronized update the
+    /// previous frame stays on screen, so that it is never shown half drawn.
+    fn present(&mut self) {
+        if self.parser.take_frame_ready() {
+            self.terminal_cache.clear();
+        }
     }
 
     /// Update the application state
@@ -82,25 +263,73 @@ impl TermiEmuApp {
             Message::TerminalOutput(data) => {
                 // Process terminal output through parser
                 self.parser.advance_bytes(&data);
+
+                self.handle_clipboard_requests();
+                self.handle_notifications();
+
+                // Answer device queries (DA, DSR, CPR, DECRQM)
+                if self.parser.has_responses() {
+                    let responses = self.parser.take_responses();
+                    self.write_to_pty(&responses);
+                }
+                self.present();
                 Task::none()
             }
             Message::KeyPressed(event) => {
+                // Typing keeps the cursor visible
+                self.cursor_blink_on = true;
                 self.handle_keyboard(event);
+                self.present();
+                Task::none()
+            }
+            Message::WindowFocused(focused) => {
+                self.focused = focused;
+                self.cursor_blink_on = true;
+                self.present();
+                Task::none()
+            }
+            Message::CursorBlink => {
+                self.cursor_blink_on = !self.cursor_blink_on;
+                self.present();
+                Task::none()
+            }
+            Message::SynchronizedOutputTimeout => {
+                // Show what there is rather than freezing the screen
+                debug!("Synchronized update timed out, presenting anyway");
+                self.parser.end_synchronized_update();
+                self.present();
                 Task::none()
             }
         }
     }
 
     /// Subscribe to events
+    ///
+    /// A blinking cursor also subscribes to the blink timer, which stops
+    /// while the window is unfocused. An unfinished synchronized update
+    /// subscribes to its timeout.
     pub fn subscription(&self) -> Subscription<Message> {
-        iced::event::listen_with(|event, _status, _id| {
-            if let Event::Keyboard(kbd_event) = event {
-                Some(Message::KeyPressed(kbd_event))
-            } else {
+        let blink = if self.parser.cursor().blinking && self.focused {
+            iced::time::every(CURSOR_BLINK_INTERVAL).map(|_| Message::CursorBlink)
+        } else {
+            Subscription::none()
+        };
+        let synchronized_output = if self.parser.is_frame_ready() {
+            Subscription::none()
+        } else {
+            iced::time::every(SYNCHRONIZED_OUTPUT_TIMEOUT)
+                .map(|_| Message::SynchronizedOutputTimeout)
+        };
+        let events = iced::event::listen_with(|event, _status, _id| {
+            match event {
+                Event::Keyboard(kbd_event) => Some(Message::KeyPressed(kbd_event)),
+                Event::Window(window::Event::Focused) => Some(Message::WindowFocused(true)),
+                Event::Window(window::Event::Unfocused) => Some(Message::WindowFocused(false)),
                 // Ignore other events for now
-                None
+                _ => None,
             }
-        })
+        });
+        Subscription::batch([events, blink, synchronized_output])
     }
 
     /// Create the view
@@ -108,37 +337,19 @@ impl TermiEmuApp {
         let grid = &self.parser.grid();
         let cursor = self.parser.cursor();
 
-        // Create a simple text-based view (for now)
-        let mut content = String::new();
-
-        // Render grid as text
-        for row in 0..grid.rows() {
-            for col in 0..grid.cols() {
-                if let Some(cell) = grid.get(col, row) {
-                    if col == cursor.col && row == cursor.row && cursor.visible {
-                        // Show cursor: use block for empty space, preserve char for content
-                        // Note: proper cursor styling will be done with custom widget in Phase 1
-                        if cell.c == ' ' {
-                            content.push('█');
-                        } else {
-                            // For non-empty cells, show the character
-                            // (inverse video would be applied in a proper renderer)
-                            content.push(cell.c);
-                        }
-                    } else {
-                        content.push(cell.c);
-                    }
-                } else {
-                    content.push(' ');
-                }
-            }
-            content.push('\n');
+        let mut terminal = TerminalCanvas::new(grid, self.parser.palette(), &self.terminal_cache);
+        if cursor.visible && (self.cursor_blink_on || !cursor.blinking) {
+            terminal = terminal.with_cursor(cursor.col, cursor.row, cursor.style);
         }
-
-        let terminal_view = text(content).font(iced::Font::MONOSPACE).size(14);
+        let size = terminal.size();
+        let terminal_view = canvas(terminal).width(size.width).height(size.height);
 
         let status_text = if let Some(ref err) = self.error {
             format!("Error: {}", err)
+        } else if self.pending_clipboard_read.is_some() {
+            String::from(
+                "An application wants to read the clipboard: Ctrl+Shift+Y to allow, any other key to deny",
+            )
         } else if self.pty.is_some() {
             format!(
                 "Grid: {}x{} | Cursor: ({}, {}) | PTY: Active",
@@ -173,13 +384,13 @@ impl TermiEmuApp {
     }
 }
 
-/// Spawn PTY synchronously
-fn spawn_pty() -> anyhow::Result<Pty> {
+/// Spawn PTY synchronously, optionally in the given working directory
+fn spawn_pty(working_directory: Option<PathBuf>) -> anyhow::Result<Pty> {
     let config = PtyConfig {
         rows: 24,
         cols: 80,
         shell: None,
-        working_directory: None,
+        working_directory,
         env: vec![("TERM".to_string(), "xterm-256color".to_string())],
     };
 
@@ -189,6 +400,48 @@ fn spawn_pty() -> anyhow::Result<Pty> {
     Ok(pty)
 }
 
+/// Get the notifier for the desktop, falling back to logging notifications
+/// where none is available
+fn desktop_notifier() -> Box<dyn Notifier> {
+    #[cfg(target_os = "linux")]
+    match crate::notification::DbusNotifier::new() {
+        Ok(notifier) => return Box::new(notifier),
+        Err(e) => warn!("Desktop notifications are unavailable: {}", e),
+    }
+    Box::new(LoggingNotifier::new())
+}
+
+/// Answer of a key press to a pending clipboard read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClipboardPromptAnswer {
+    /// Ctrl+Shift+Y allows the read
+    Allow,
+    /// Any other key denies it
+    Deny,
+    /// Modifier keys on their own leave the prompt open, since they are
+    /// pressed on the way to Ctrl+Shift+Y
+    Undecided,
+}
+
+/// Get the answer of a key press to a pending clipboard read
+fn clipboard_prompt_answer(
+    key: &keyboard::Key,
+    modifiers: keyboard::Modifiers,
+) -> ClipboardPromptAnswer {
+    use keyboard::key::Named;
+    match key {
+        keyboard::Key::Named(Named::Control | Named::Shift | Named::Alt | Named::Super) => {
+            ClipboardPromptAnswer::Undecided
+        }
+        keyboard::Key::Character(s)
+            if s.eq_ignore_ascii_case("y") && modifiers.control() && modifiers.shift() =>
+        {
+            ClipboardPromptAnswer::Allow
+        }
+        _ => ClipboardPromptAnswer::Deny,
+    }
+}
+
 /// Convert keyboard key and modifiers to bytes for PTY
 fn key_to_bytes(key: keyboard::Key, modifiers: keyboard::Modifiers) -> Option<Vec<u8>> {
     use keyboard::Key;
@@ -254,3 +507,39 @@ fn key_to_bytes(key: keyboard::Key, modifiers: keyboard::Modifiers) -> Option<Ve
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keyboard::key::Named;
+    use keyboard::{Key, Modifiers};
+
+    #[test]
+    fn test_clipboard_prompt_waits_for_the_whole_chord() {
+        let presses = [
+            (Key::Named(Named::Control), Modifiers::CTRL),
+            (Key::Named(Named::Shift), Modifiers::CTRL | Modifiers::SHIFT),
+            (Key::Character("Y".into()), Modifiers::CTRL | Modifiers::SHIFT),
+        ];
+        let answers: Vec<_> = presses
+            .iter()
+            .map(|(key, modifiers)| clipboard_prompt_answer(key, *modifiers))
+            .collect();
+        assert_eq!(
+            answers,
+            [
+                ClipboardPromptAnswer::Undecided,
+                ClipboardPromptAnswer::Undecided,
+                ClipboardPromptAnswer::Allow
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clipboard_prompt_denied_by_other_keys() {
+        let answer = clipboard_prompt_answer(&Key::Character("y".into()), Modifiers::CTRL);
+        assert_eq!(answer, ClipboardPromptAnswer::Deny);
+        let answer = clipboard_prompt_answer(&Key::Named(Named::Enter), Modifiers::empty());
+        assert_eq!(answer, ClipboardPromptAnswer::Deny);
+    }
+}
@@ -1,8 +1,230 @@
-//! Custom terminal widget for rendering the terminal grid
+//! Canvas widget rendering the terminal grid
 //!
-//! This module will eventually contain a custom canvas-based widget
-//! for high-performance terminal rendering with cosmic-text.
-//! For Phase 0, we use simple text rendering.
+//! Each cell is drawn as its background, its grapheme cluster and its text
+//! decorations. Underlines are drawn as geometry rather than with the font,
+//! so that the double, curly, dotted and dashed styles and the separate
+//! underline color (SGR 58) can be shown.
+//!
+//! The geometry is kept in a cache owned by the caller, which decides when a
+//! new frame is presented by clearing it.
+
+use crate::config::theme::Rgb;
+use crate::terminal::{Cell, CellFlags, CursorStyle, Grid, Palette, SpecialColor, UnderlineStyle};
+use iced::alignment;
+use iced::font::{Style, Weight};
+use iced::widget::canvas::{self, path, Frame, Geometry, LineDash, Path, Stroke, Text};
+use iced::widget::text::{LineHeight, Shaping};
+use iced::{mouse, Color, Font, Pixels, Point, Rectangle, Renderer, Size, Theme};
+
+/// Font size of the terminal text
+pub const FONT_SIZE: f32 = 14.0;
+/// Width of a cell in pixels
+pub const CELL_WIDTH: f32 = FONT_SIZE * 0.6;
+/// Height of a cell in pixels
+pub const CELL_HEIGHT: f32 = FONT_SIZE * 1.3;
+
+/// Thickness of underlines, overlines and strikethrough
+const LINE_WIDTH: f32 = 1.0;
+/// Thickness of the underline and bar cursors
+const CURSOR_THICKNESS: f32 = 2.0;
+
+/// Canvas program drawing a terminal grid
+pub struct TerminalCanvas<'a> {
+    grid: &'a Grid,
+    palette: &'a Palette,
+    /// Geometry of the last presented frame
+    cache: &'a canvas::Cache,
+    /// Column, row and shape of the visible cursor
+    cursor: Option<(usize, usize, CursorStyle)>,
+}
+
+impl<'a> TerminalCanvas<'a> {
+    /// Create a program drawing `grid` with the colors of `palette`
+    ///
+    /// The grid is only drawn again once `cache` has been cleared.
+    pub fn new(grid: &'a Grid, palette: &'a Palette, cache: &'a canvas::Cache) -> Self {
+        Self { grid, palette, cache, cursor: None }
+    }
+
+    /// Draw a cursor of the given shape at a cell
+    pub fn with_cursor(mut self, col: usize, row: usize, style: CursorStyle) -> Self {
+        self.cursor = Some((col, row, style));
+        self
+    }
+
+    /// Get the size needed to show the whole grid
+    pub fn size(&self) -> Size {
+        Size::new(self.grid.cols() as f32 * CELL_WIDTH, self.grid.rows() as f32 * CELL_HEIGHT)
+    }
+
+    /// Draw one cell
+    fn draw_cell(&self, frame: &mut Frame, cell: &Cell, col: usize, row: usize) {
+        let origin = Point::new(col as f32 * CELL_WIDTH, row as f32 * CELL_HEIGHT);
+        // Only a block cursor changes how the cell itself is drawn
+        let is_cursor = self.cursor == Some((col, row, CursorStyle::Block));
+
+        let (mut fg, mut bg) = (self.palette.resolve(cell.fg), self.palette.resolve(cell.bg));
+        if cell.flags.contains(CellFlags::INVERSE) != is_cursor {
+            std::mem::swap(&mut fg, &mut bg);
+        }
+        if is_cursor {
+            bg = self.palette.special(SpecialColor::Cursor);
+        }
+        let mut fg = to_color(fg);
+        if cell.flags.contains(CellFlags::DIM) {
+            fg.a = 0.6;
+        }
+
+        let width = if cell.flags.contains(CellFlags::WIDE_CHAR) { 2.0 } else { 1.0 };
+        let background = self.palette.special(SpecialColor::Background);
+        if bg != background || is_cursor {
+            frame.fill_rectangle(origin, Size::new(CELL_WIDTH * width, CELL_HEIGHT), to_color(bg));
+        }
+
+        if cell.flags.contains(CellFlags::HIDDEN) {
+            return;
+        }
+
+        if !cell.flags.contains(CellFlags::WIDE_CHAR_SPACER) && cell.c != ' ' {
+            let mut content = String::from(cell.c);
+            content.push_str(self.grid.zerowidth(cell));
+            frame.fill_text(Text {
+                content,
+                position: origin,
+                color: fg,
+                size: Pixels(FONT_SIZE),
+                line_height: LineHeight::Absolute(Pixels(CELL_HEIGHT)),
+                font: cell_font(cell.flags),
+                horizontal_alignment: alignment::Horizontal::Left,
+                vertical_alignment: alignment::Vertical::Top,
+                shaping: Shaping::Advanced,
+            });
+        }
+
+        // Decorations of a wide character are drawn per half, since its
+        // spacer carries the same flags
+        if let Some(style) = cell.flags.underline_style() {
+            let color = self
+                .grid
+                .cell_extra(cell)
+                .and_then(|extra| extra.underline_color)
+                .map_or(fg, |color| to_color(self.palette.resolve(color)));
+            draw_underline(frame, style, origin, color);
+        }
+        if cell.flags.contains(CellFlags::OVERLINE) {
+            draw_line(frame, origin.x, origin.y + LINE_WIDTH, fg, LineDash::default());
+        }
+        if cell.flags.contains(CellFlags::STRIKETHROUGH) {
+            let y = origin.y + CELL_HEIGHT * 0.5;
+            draw_line(frame, origin.x, y, fg, LineDash::default());
+        }
+    }
+}
+
+impl<Message> canvas::Program<Message> for TerminalCanvas<'_> {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        renderer: &Renderer,
+        _theme: &Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let geometry = self.cache.draw(renderer, bounds.size(), |frame| {
+            let background = to_color(self.palette.special(SpecialColor::Background));
+            frame.fill_rectangle(Point::ORIGIN, bounds.size(), background);
+
+            for row in 0..self.grid.rows() {
+                for col in 0..self.grid.cols() {
+                    if let Some(cell) = self.grid.get(col, row) {
+                        self.draw_cell(frame, cell, col, row);
+                    }
+                }
+            }
+
+            if let Some((col, row, style)) = self.cursor {
+                let origin = Point::new(col as f32 * CELL_WIDTH, row as f32 * CELL_HEIGHT);
+                let color = to_color(self.palette.special(SpecialColor::Cursor));
+                match style {
+                    // Drawn with the cell
+                    CursorStyle::Block => {}
+                    CursorStyle::Underline => frame.fill_rectangle(
+                        Point::new(origin.x, origin.y + CELL_HEIGHT - CURSOR_THICKNESS),
+                        Size::new(CELL_WIDTH, CURSOR_THICKNESS),
+                        color,
+                    ),
+                    CursorStyle::Bar => frame.fill_rectangle(
+                        origin,
+                        Size::new(CURSOR_THICKNESS, CELL_HEIGHT),
+                        color,
+                    ),
+                }
+            }
+        });
+
+        vec![geometry]
+    }
+}
+
+/// Draw an underline below the cell at `origin`
+fn draw_underline(frame: &mut Frame, style: UnderlineStyle, origin: Point, color: Color) {
+    let y = origin.y + CELL_HEIGHT - 2.0 * LINE_WIDTH;
+    match style {
+        UnderlineStyle::Single => draw_line(frame, origin.x, y, color, LineDash::default()),
+        UnderlineStyle::Double => {
+            draw_line(frame, origin.x, y, color, LineDash::default());
+            draw_line(frame, origin.x, y - 2.0 * LINE_WIDTH, color, LineDash::default());
+        }
+        UnderlineStyle::Dotted => {
+            let dash = LineDash { segments: &[LINE_WIDTH, LINE_WIDTH], offset: 0 };
+            draw_line(frame, origin.x, y, color, dash);
+        }
+        UnderlineStyle::Dashed => {
+            let segment = CELL_WIDTH / 4.0;
+            let dash = LineDash { segments: &[segment, segment], offset: 0 };
+            draw_line(frame, origin.x, y, color, dash);
+        }
+        UnderlineStyle::Curly => {
+            // One wave per cell, so that adjacent cells join up
+            let amplitude = 1.5 * LINE_WIDTH;
+            let y = y - amplitude;
+            let quarter = CELL_WIDTH / 4.0;
+            let mut builder = path::Builder::new();
+            builder.move_to(Point::new(origin.x, y));
+            builder.quadratic_curve_to(
+                Point::new(origin.x + quarter, y - amplitude * 2.0),
+                Point::new(origin.x + quarter * 2.0, y),
+            );
+            builder.quadratic_curve_to(
+                Point::new(origin.x + quarter * 3.0, y + amplitude * 2.0),
+                Point::new(origin.x + CELL_WIDTH, y),
+            );
+            frame.stroke(&builder.build(), line_stroke(color, LineDash::default()));
+        }
+    }
+}
+
+/// Draw a horizontal line across one cell
+fn draw_line(frame: &mut Frame, x: f32, y: f32, color: Color, dash: LineDash<'_>) {
+    let line = Path::line(Point::new(x, y), Point::new(x + CELL_WIDTH, y));
+    frame.stroke(&line, line_stroke(color, dash));
+}
+
+fn line_stroke(color: Color, dash: LineDash<'_>) -> Stroke<'_> {
+    Stroke { line_dash: dash, ..Stroke::default().with_color(color).with_width(LINE_WIDTH) }
+}
+
+/// Get the font for the attributes of a cell
+fn cell_font(flags: CellFlags) -> Font {
+    Font {
+        weight: if flags.contains(CellFlags::BOLD) { Weight::Bold } else { Weight::Normal },
+        style: if flags.contains(CellFlags::ITALIC) { Style::Italic } else { Style::Normal },
+        ..Font::MONOSPACE
+    }
+}
 
-// Placeholder for future custom widget implementation
-// Will be implemented in later phases for performance optimization
+fn to_color(rgb: Rgb) -> Color {
+    Color::from_rgb8(rgb.r, rgb.g, rgb.b)
+}
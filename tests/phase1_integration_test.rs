@@ -11,7 +11,7 @@
 use termiemu::{
     clipboard::ClipboardManager,
     config::{Config, Theme},
-    terminal::{Parser, Point, Selection, TerminalModes},
+    terminal::{Parser, Point, Selection},
 };
 
 /// Test alternate screen buffer switching
@@ -331,8 +331,7 @@ fn test_full_terminal_session() {
 /// Integration test: Configuration with theme
 #[test]
 fn test_config_with_theme() {
-    let mut config = Config::default();
-    config.theme = "tokyo-night".to_string();
+    let config = Config { theme: "tokyo-night".to_string(), ..Config::default() };
 
     // Validate config
     assert!(config.validate().is_ok());
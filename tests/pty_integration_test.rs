@@ -62,8 +62,8 @@ fn test_pty_resize_multiple_times() {
 
 #[test]
 fn test_pty_with_custom_working_directory() {
-    let mut config = PtyConfig::default();
-    config.working_directory = Some(std::env::temp_dir());
+    let config =
+        PtyConfig { working_directory: Some(std::env::temp_dir()), ..PtyConfig::default() };
 
     let mut pty = Pty::new(config).expect("Failed to create PTY");
     pty.spawn().expect("Failed to spawn shell");
@@ -75,11 +75,13 @@ fn test_pty_with_custom_working_directory() {
 
 #[test]
 fn test_pty_with_environment_variables() {
-    let mut config = PtyConfig::default();
-    config.env = vec![
-        ("TEST_VAR_1".to_string(), "value1".to_string()),
-        ("TEST_VAR_2".to_string(), "value2".to_string()),
-    ];
+    let config = PtyConfig {
+        env: vec![
+            ("TEST_VAR_1".to_string(), "value1".to_string()),
+            ("TEST_VAR_2".to_string(), "value2".to_string()),
+        ],
+        ..PtyConfig::default()
+    };
 
     let mut pty = Pty::new(config).expect("Failed to create PTY");
     pty.spawn().expect("Failed to spawn shell");
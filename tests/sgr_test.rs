@@ -0,0 +1,168 @@
+//! Table-driven tests for SGR (Select Graphic Rendition)
+//!
+//! Each case feeds a sequence to a fresh parser, prints one character and
+//! checks the attributes it was drawn with. Extended colors are covered in
+//! both the colon (ITU T.416) and the semicolon (xterm) encoding.
+
+use termiemu::terminal::{CellFlags, Color, NamedColor, Parser};
+
+/// Expected attributes of the printed character
+struct Case {
+    name: &'static str,
+    input: &'static [u8],
+    fg: Color,
+    bg: Color,
+    flags: CellFlags,
+    underline_color: Option<Color>,
+}
+
+const FG: Color = Color::Named(NamedColor::Foreground);
+const BG: Color = Color::Named(NamedColor::Background);
+
+const fn case(name: &'static str, input: &'static [u8]) -> Case {
+    Case { name, input, fg: FG, bg: BG, flags: CellFlags::empty(), underline_color: None }
+}
+
+impl Case {
+    const fn fg(self, fg: Color) -> Self {
+        Self { fg, ..self }
+    }
+
+    const fn bg(self, bg: Color) -> Self {
+        Self { bg, ..self }
+    }
+
+    const fn flags(self, flags: CellFlags) -> Self {
+        Self { flags, ..self }
+    }
+
+    const fn underline_color(self, color: Color) -> Self {
+        Self { underline_color: Some(color), ..self }
+    }
+}
+
+fn run(cases: &[Case]) {
+    for case in cases {
+        let mut parser = Parser::new(10, 2);
+        parser.advance_bytes(case.input);
+        parser.advance_bytes(b"x");
+
+        let grid = parser.grid();
+        let cell = grid.get(0, 0).unwrap();
+        let underline_color = grid.cell_extra(cell).and_then(|extra| extra.underline_color);
+        assert_eq!(cell.c, 'x', "{}: sequence was not consumed", case.name);
+        assert_eq!(cell.fg, case.fg, "{}: foreground", case.name);
+        assert_eq!(cell.bg, case.bg, "{}: background", case.name);
+        assert_eq!(cell.flags, case.flags, "{}: flags", case.name);
+        assert_eq!(underline_color, case.underline_color, "{}: underline color", case.name);
+    }
+}
+
+#[test]
+fn test_sgr_attributes() {
+    run(&[
+        case("bold", b"\x1b[1m").flags(CellFlags::BOLD),
+        case("dim", b"\x1b[2m").flags(CellFlags::DIM),
+        case("italic", b"\x1b[3m").flags(CellFlags::ITALIC),
+        case("underline", b"\x1b[4m").flags(CellFlags::UNDERLINE),
+        case("slow blink", b"\x1b[5m").flags(CellFlags::BLINK),
+        case("rapid blink", b"\x1b[6m").flags(CellFlags::BLINK),
+        case("inverse", b"\x1b[7m").flags(CellFlags::INVERSE),
+        case("hidden", b"\x1b[8m").flags(CellFlags::HIDDEN),
+        case("strikethrough", b"\x1b[9m").flags(CellFlags::STRIKETHROUGH),
+        case("double underline", b"\x1b[21m").flags(CellFlags::DOUBLE_UNDERLINE),
+        case("overline", b"\x1b[53m").flags(CellFlags::OVERLINE),
+        case("combined", b"\x1b[1;3;53m")
+            .flags(CellFlags::BOLD.union(CellFlags::ITALIC).union(CellFlags::OVERLINE)),
+        case("unknown parameters are skipped", b"\x1b[1;66;3m")
+            .flags(CellFlags::BOLD.union(CellFlags::ITALIC)),
+    ]);
+}
+
+#[test]
+fn test_sgr_attribute_resets() {
+    run(&[
+        case("22 clears bold and dim", b"\x1b[1;2;3m\x1b[22m").flags(CellFlags::ITALIC),
+        case("23", b"\x1b[3m\x1b[23m"),
+        case("24 clears every underline style", b"\x1b[4:3m\x1b[24m"),
+        case("25", b"\x1b[5m\x1b[25m"),
+        case("27", b"\x1b[7m\x1b[27m"),
+        case("28", b"\x1b[8m\x1b[28m"),
+        case("29", b"\x1b[9m\x1b[29m"),
+        case("55", b"\x1b[53m\x1b[55m"),
+        case("0 resets everything", b"\x1b[1;4;31;42;58;5;3m\x1b[0m"),
+        case("empty parameters reset", b"\x1b[1;31m\x1b[m"),
+        case("reset in the middle", b"\x1b[1;0;3m").flags(CellFlags::ITALIC),
+    ]);
+}
+
+#[test]
+fn test_sgr_underline_styles() {
+    run(&[
+        case("4:0", b"\x1b[4m\x1b[4:0m"),
+        case("4:1", b"\x1b[4:1m").flags(CellFlags::UNDERLINE),
+        case("4:2", b"\x1b[4:2m").flags(CellFlags::DOUBLE_UNDERLINE),
+        case("4:3", b"\x1b[4:3m").flags(CellFlags::UNDERCURL),
+        case("4:4", b"\x1b[4:4m").flags(CellFlags::DOTTED_UNDERLINE),
+        case("4:5", b"\x1b[4:5m").flags(CellFlags::DASHED_UNDERLINE),
+        case("a new style replaces the old one", b"\x1b[4:3m\x1b[4m").flags(CellFlags::UNDERLINE),
+        case("unknown style is ignored", b"\x1b[4:3m\x1b[4:7m").flags(CellFlags::UNDERCURL),
+    ]);
+}
+
+#[test]
+fn test_sgr_basic_colors() {
+    run(&[
+        case("30", b"\x1b[30m").fg(Color::Named(NamedColor::Black)),
+        case("37", b"\x1b[37m").fg(Color::Named(NamedColor::White)),
+        case("90", b"\x1b[90m").fg(Color::Named(NamedColor::BrightBlack)),
+        case("97", b"\x1b[97m").fg(Color::Named(NamedColor::BrightWhite)),
+        case("41", b"\x1b[41m").bg(Color::Named(NamedColor::Red)),
+        case("104", b"\x1b[104m").bg(Color::Named(NamedColor::BrightBlue)),
+        case("39", b"\x1b[31m\x1b[39m"),
+        case("49", b"\x1b[41m\x1b[49m"),
+    ]);
+}
+
+#[test]
+fn test_sgr_extended_colors() {
+    run(&[
+        case("38;5 semicolon", b"\x1b[38;5;196m").fg(Color::Indexed(196)),
+        case("38:5 colon", b"\x1b[38:5:196m").fg(Color::Indexed(196)),
+        case("low indexes are named colors", b"\x1b[38;5;1m").fg(Color::Named(NamedColor::Red)),
+        case("48;5 semicolon", b"\x1b[48;5;17m").bg(Color::Indexed(17)),
+        case("48:5 colon", b"\x1b[48:5:17m").bg(Color::Indexed(17)),
+        case("38;2 semicolon", b"\x1b[38;2;10;20;30m").fg(Color::Rgb(10, 20, 30)),
+        case("38:2 colon", b"\x1b[38:2:10:20:30m").fg(Color::Rgb(10, 20, 30)),
+        case("38:2 colon with color space", b"\x1b[38:2:0:10:20:30m").fg(Color::Rgb(10, 20, 30)),
+        case("38:2 colon with empty color space", b"\x1b[38:2::10:20:30m")
+            .fg(Color::Rgb(10, 20, 30)),
+        case("48;2 semicolon", b"\x1b[48;2;1;2;3m").bg(Color::Rgb(1, 2, 3)),
+        case("48:2 colon", b"\x1b[48:2::1:2:3m").bg(Color::Rgb(1, 2, 3)),
+        case("58;2 semicolon", b"\x1b[58;2;4;5;6m").underline_color(Color::Rgb(4, 5, 6)),
+        case("58:5 colon", b"\x1b[58:5:99m").underline_color(Color::Indexed(99)),
+        case("59", b"\x1b[58;5;99m\x1b[59m"),
+        case("parameters after a semicolon color", b"\x1b[38;2;1;2;3;1;48;5;4m")
+            .fg(Color::Rgb(1, 2, 3))
+            .bg(Color::Named(NamedColor::Blue))
+            .flags(CellFlags::BOLD),
+        case("parameters after a colon color", b"\x1b[38:2::1:2:3;1m")
+            .fg(Color::Rgb(1, 2, 3))
+            .flags(CellFlags::BOLD),
+    ]);
+}
+
+#[test]
+fn test_sgr_malformed_extended_colors() {
+    run(&[
+        case("index out of range", b"\x1b[31m\x1b[38;5;256m").fg(Color::Named(NamedColor::Red)),
+        case("component out of range", b"\x1b[38;2;1;2;300m"),
+        case("colon component out of range", b"\x1b[48:2::1:999:3m"),
+        case("missing index", b"\x1b[38;5m"),
+        case("missing components", b"\x1b[38;2;1;2m"),
+        case("missing colon components", b"\x1b[38:2:1m"),
+        case("unknown color kind", b"\x1b[38;7;1m").flags(CellFlags::BOLD),
+        case("missing kind", b"\x1b[38m"),
+        case("colon color does not consume parameters", b"\x1b[38:5;1m").flags(CellFlags::BOLD),
+    ]);
+}
@@ -0,0 +1,195 @@
+//! VT100 conformance tests
+//!
+//! Each test draws a screen in the spirit of one of vttest's screens and
+//! compares the whole grid against the expected picture, so that cursor
+//! movement, wrapping and editing have to agree with each other and not
+//! just with individual unit tests.
+
+use termiemu::terminal::Parser;
+
+/// Get the visible screen as one string per row
+fn screen(parser: &Parser) -> Vec<String> {
+    let grid = parser.grid();
+    (0..grid.rows())
+        .map(|row| (0..grid.cols()).map(|col| grid.get(col, row).unwrap().c).collect())
+        .collect()
+}
+
+fn cursor(parser: &Parser) -> (usize, usize) {
+    (parser.cursor().col, parser.cursor().row)
+}
+
+/// vttest 1: a box drawn with absolute and relative cursor movements
+/// inside a screen filled by DECALN
+#[test]
+fn test_cursor_movements_draw_box() {
+    let mut parser = Parser::new(10, 6);
+    parser.advance_bytes(b"\x1b#8");
+    // Blank the inside of the frame
+    for row in 2..=5 {
+        parser.advance_bytes(format!("\x1b[{};2H\x1b[8X", row).as_bytes());
+    }
+    // Top and bottom edges with CUP
+    parser.advance_bytes(b"\x1b[2;2H********\x1b[5;2H********");
+    // Left edge with CUB and CUD, right edge with BS and IND
+    parser.advance_bytes(b"\x1b[3;2H*\x1b[D\x1b[B*");
+    parser.advance_bytes(b"\x1b[3;9H*\x08\x1bD*");
+
+    assert_eq!(
+        screen(&parser),
+        ["EEEEEEEEEE", "E********E", "E*      *E", "E*      *E", "E********E", "EEEEEEEEEE"]
+    );
+}
+
+/// vttest 1: CUU/CUD/CUF/CUB stop at the screen edges
+#[test]
+fn test_cursor_movements_stop_at_edges() {
+    let mut parser = Parser::new(10, 6);
+    parser.advance_bytes(b"\x1b[3;3H\x1b[20A");
+    assert_eq!(cursor(&parser), (2, 0));
+    parser.advance_bytes(b"\x1b[20B");
+    assert_eq!(cursor(&parser), (2, 5));
+    parser.advance_bytes(b"\x1b[20C");
+    assert_eq!(cursor(&parser), (9, 5));
+    parser.advance_bytes(b"\x1b[20D");
+    assert_eq!(cursor(&parser), (0, 5));
+}
+
+/// vttest 1: autowrap on and off, mixed with control characters
+#[test]
+fn test_autowrap_mixing_control_and_print_characters() {
+    let mut parser = Parser::new(10, 6);
+    parser.advance_bytes(b"ABCDEFGHIJKL");
+    // Without autowrap the last column is overwritten
+    parser.advance_bytes(b"\x1b[?7l\x1b[3;1HABCDEFGHIJKL\x1b[?7h");
+    // CR and BS in the last column cancel the pending wrap
+    parser.advance_bytes(b"\x1b[4;1Habcdefghij\rX\x1b[4;10Hy\x08Z");
+
+    assert_eq!(
+        screen(&parser),
+        ["ABCDEFGHIJ", "KL        ", "ABCDEFGHIL", "XbcdefghZy", "          ", "          "]
+    );
+}
+
+/// vttest 1: LF, VT and FF all move down a line without a carriage return
+#[test]
+fn test_vertical_tab_and_form_feed_are_line_feeds() {
+    let mut parser = Parser::new(10, 6);
+    parser.advance_bytes(b"A\nB\x0bC\x0cD");
+    assert_eq!(screen(&parser)[..4], ["A         ", " B        ", "  C       ", "   D      "]);
+}
+
+/// vttest 1: origin mode homes the cursor and confines it to the
+/// scrolling region
+#[test]
+fn test_origin_mode() {
+    let mut parser = Parser::new(10, 6);
+    parser.advance_bytes(b"\x1b[3;5r\x1b[5;5H\x1b[?6h");
+    // Setting DECOM homes the cursor to the top margin
+    assert_eq!(cursor(&parser), (0, 2));
+
+    // CUP is relative to the region and clamped to it
+    parser.advance_bytes(b"\x1b[1;1HA\x1b[9;3HB");
+    assert_eq!(cursor(&parser), (3, 4));
+
+    // CPR reports the position relative to the region
+    parser.advance_bytes(b"\x1b[6n");
+    assert_eq!(parser.take_responses(), b"\x1b[3;4R");
+
+    // CUU and CUD stop at the margins
+    parser.advance_bytes(b"\x1b[9A");
+    assert_eq!(cursor(&parser), (3, 2));
+    parser.advance_bytes(b"\x1b[9B");
+    assert_eq!(cursor(&parser), (3, 4));
+
+    // Resetting DECOM homes the cursor to the top of the screen
+    parser.advance_bytes(b"\x1b[?6l");
+    assert_eq!(cursor(&parser), (0, 0));
+
+    assert_eq!(screen(&parser)[2..5], ["A         ", "          ", "  B       "]);
+}
+
+/// vttest 1: line feeds at the bottom margin scroll only the region
+#[test]
+fn test_origin_mode_scrolls_region() {
+    let mut parser = Parser::new(10, 6);
+    parser.advance_bytes(b"top\x1b[6;1Hbottom");
+    parser.advance_bytes(b"\x1b[2;4r\x1b[?6h1\r\n2\r\n3\r\n4\r\n5");
+
+    assert_eq!(
+        screen(&parser),
+        ["top       ", "3         ", "4         ", "5         ", "          ", "bottom    "]
+    );
+    assert_eq!(parser.grid().scrollback_len(), 0);
+}
+
+/// vttest 8: insert mode shifts the rest of the line right
+#[test]
+fn test_insert_mode() {
+    let mut parser = Parser::new(10, 6);
+    parser.advance_bytes(b"ABCDEFGHIJ\x1b[1;3H\x1b[4hxy\x1b[4lz");
+    // Characters pushed past the right margin are lost
+    assert_eq!(screen(&parser)[0], "ABxyzDEFGH");
+
+    // Without a pending wrap insert mode never wraps by itself
+    parser.advance_bytes(b"\x1b[2;1Habcdefghij\x1b[2;10H\x1b[4hK\x1b[4l");
+    assert_eq!(screen(&parser)[1], "abcdefghiK");
+}
+
+/// Insert mode never leaves half of a wide character at the margin
+#[test]
+fn test_insert_mode_with_wide_characters() {
+    let mut parser = Parser::new(10, 6);
+    parser.advance_bytes("abcdefgh中".as_bytes());
+    parser.advance_bytes(b"\x1b[1;1H\x1b[4hZ");
+    assert_eq!(screen(&parser)[0], "Zabcdefgh ");
+
+    // Inserting a wide character shifts by two columns
+    parser.advance_bytes("\x1b[2;1Habcdefghij\x1b[2;1H中".as_bytes());
+    assert_eq!(screen(&parser)[1], "中 abcdefgh");
+}
+
+/// vttest 8: ICH and DCH
+#[test]
+fn test_insert_and_delete_characters() {
+    let mut parser = Parser::new(10, 6);
+    parser.advance_bytes(b"1234567890\x1b[1;4H\x1b[2P");
+    assert_eq!(screen(&parser)[0], "12367890  ");
+    parser.advance_bytes(b"\x1b[2@");
+    assert_eq!(screen(&parser)[0], "123  67890");
+}
+
+/// vttest 11 (VT420): text wraps and scrolls inside left/right margins
+#[test]
+fn test_left_right_margins() {
+    let mut parser = Parser::new(10, 6);
+    parser.advance_bytes(b"\x1b#8\x1b[?69h\x1b[3;8s\x1b[2;5r\x1b[5;3H012345");
+    // Wrapping at the bottom margin scrolls only the area inside the margins
+    parser.advance_bytes(b"6789ab");
+
+    assert_eq!(
+        screen(&parser),
+        ["EEEEEEEEEE", "EEEEEEEEEE", "EEEEEEEEEE", "EE012345EE", "EE6789abEE", "EEEEEEEEEE"]
+    );
+    assert_eq!(cursor(&parser), (7, 4));
+}
+
+/// vttest 11 (VT420): rectangular operations are relative to the margins
+/// in origin mode
+#[test]
+fn test_rectangular_operations_in_origin_mode() {
+    let mut parser = Parser::new(10, 6);
+    parser.advance_bytes(b"\x1b[?69h\x1b[3;8s\x1b[2;5r\x1b[?6h");
+    parser.advance_bytes(b"\x1b[42;1;1;2;3$x\x1b[1;1;2;3;1;3;4;1$v");
+    assert_eq!(
+        screen(&parser),
+        ["          ", "  ***     ", "  ***     ", "     ***  ", "     ***  ", "          "]
+    );
+
+    // A default rectangle covers exactly the area inside the margins
+    parser.advance_bytes(b"\x1b[?6l\x1b#8\x1b[?69h\x1b[3;8s\x1b[2;5r\x1b[?6h\x1b[$z");
+    assert_eq!(
+        screen(&parser),
+        ["EEEEEEEEEE", "EE      EE", "EE      EE", "EE      EE", "EE      EE", "EEEEEEEEEE"]
+    );
+}